@@ -0,0 +1,46 @@
+use std::{
+    ffi::OsString,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+/// Writes a file by passing a writer for a temporary file next to it to `f`,
+/// then renaming the temporary file into place once everything has been written and flushed.
+///
+/// If `f` fails or the program is interrupted, the file at `path` is left untouched,
+/// so a partially written file is never mistaken for a complete one.
+pub fn write_atomic<T>(
+    path: &Path,
+    f: impl FnOnce(&mut BufWriter<File>) -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let temp_path = temp_path(path);
+    let result = File::create(&temp_path)
+        .map_err(anyhow::Error::from)
+        .and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            let result = f(&mut writer)?;
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+            Ok(result)
+        })
+        .and_then(|result| {
+            std::fs::rename(&temp_path, path)?;
+            Ok(result)
+        });
+    if result.is_err() {
+        // Don't leave a half-written file behind.
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// The temporary file that [`write_atomic`] writes to before renaming it to `path`.
+fn temp_path(path: &Path) -> PathBuf {
+    let mut temp_path = OsString::from(path.as_os_str());
+    temp_path.push(".tmp");
+    PathBuf::from(temp_path)
+}
@@ -1,10 +1,13 @@
 use std::{
     fs::File,
-    io::{BufRead, BufReader, Seek},
+    io::{BufRead, BufReader, Read, Seek},
 };
 
 /// Returns the next complete line in the given file starting at the given byte offset.
-fn next_line_starting_at(file: &mut File, start: u64) -> anyhow::Result<Option<String>> {
+fn next_line_starting_at(
+    file: &mut (impl Read + Seek),
+    start: u64,
+) -> anyhow::Result<Option<String>> {
     file.seek(std::io::SeekFrom::Start(start))?;
     // We'll use a very small capacity because lines are short.
     let mut reader = BufReader::with_capacity(0x200, file);
@@ -21,13 +24,46 @@ fn next_line_starting_at(file: &mut File, start: u64) -> anyhow::Result<Option<S
     Ok(Some(String::from_utf8(buf)?))
 }
 
+/// Returns the first non-empty line in the given file, without its trailing newline.
+pub fn first_line_in_file(file: &mut File) -> anyhow::Result<Option<String>> {
+    file.seek(std::io::SeekFrom::Start(0))?;
+    for line in BufReader::with_capacity(0x200, file).lines() {
+        let line = line?;
+        if !line.is_empty() {
+            return Ok(Some(line));
+        }
+    }
+    Ok(None)
+}
+
+/// Returns the last non-empty line in the given file, without its trailing newline.
+/// This assumes that lines are short, since we only read the end of the file.
+pub fn last_line_in_file(file: &mut File) -> anyhow::Result<Option<String>> {
+    const TAIL_LENGTH: u64 = 0x1000;
+    let len = file.metadata()?.len();
+    let start = len.saturating_sub(TAIL_LENGTH);
+    file.seek(std::io::SeekFrom::Start(start))?;
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)?;
+
+    // If `start > 0`, the first line could be incomplete, so we must not return it.
+    let lines = buf
+        .split(|b| *b == b'\n')
+        .filter(|line| !line.is_empty())
+        .skip(if start > 0 { 1 } else { 0 });
+    match lines.last() {
+        Some(line) => Ok(Some(String::from_utf8(line.to_vec())?)),
+        None => Ok(None),
+    }
+}
+
 /// Assume that `file` is a sequence of lines, such that applying `f` to each line in turn
 /// produces an increasing sequence. Then return the line that matches the given key, or [`None`]
 /// if one does not exist.
 ///
 /// Ignores empty lines.
 pub fn binary_search_line_in_file<L>(
-    file: &mut File,
+    file: &mut (impl Read + Seek),
     get_key: impl Fn(&str) -> L,
     key: &L,
 ) -> anyhow::Result<Option<String>>
@@ -35,7 +71,7 @@ where
     L: Ord,
 {
     let mut guess_min = 0u64;
-    let mut guess_max = file.metadata()?.len();
+    let mut guess_max = file.seek(std::io::SeekFrom::End(0))?;
 
     loop {
         // If the difference between `guess_max` and `guess_min` is two or less,
@@ -0,0 +1,164 @@
+use std::{
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    sync::Arc,
+};
+
+use serde::Serialize;
+
+use crate::{progress_bar::normal_progress_bar, titles::generate_title_map};
+
+use super::{
+    links::{
+        generate_in_degree, generate_incoming_links, generate_out_degree, generate_outgoing_links,
+        redirects_to_resolve, resolve_link,
+    },
+    shortest_path::SolverCache,
+};
+
+/// The number of start articles whose search levels are cached,
+/// so that queries sharing a start article don't repeat the search from it.
+const CACHE_CAPACITY: usize = 16;
+
+/// The result of a single query, written as one line of JSON to the output file.
+#[derive(Debug, Serialize)]
+struct PathRecord {
+    /// The (one-indexed) line of the input file that this query came from.
+    line: usize,
+    start: String,
+    end: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    degree: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Solves every `start<TAB>end` query in the input file, writing the results as JSON lines to the output file.
+/// Results are written in the order they finish, not the order of the input file.
+pub fn execute(input: &Path, output: &Path) -> anyhow::Result<()> {
+    let queries = BufReader::new(std::fs::File::open(input)?)
+        .lines()
+        .enumerate()
+        .map(|(i, line)| line.map(|line| (i + 1, line)))
+        .filter(|line| !matches!(line, Ok((_, line)) if line.trim().is_empty()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    println!("Loading title map");
+    let title_map = generate_title_map(true)?;
+    println!("Loading outgoing link map");
+    let outgoing_links = generate_outgoing_links(true)?;
+    println!("Loading incoming link map");
+    let incoming_links = generate_incoming_links(true)?;
+    println!("Loading degree maps");
+    let out_degree = generate_out_degree(true)?;
+    let in_degree = generate_in_degree(true)?;
+    let redirects = redirects_to_resolve(true)?;
+    println!("All data loaded.");
+
+    let progress_bar =
+        normal_progress_bar(queries.len() as u64).with_message("Solving path queries");
+
+    let (query_tx, query_rx) = crossbeam::channel::unbounded();
+    for query in queries {
+        query_tx.send(query)?;
+    }
+    drop(query_tx);
+
+    let cache = Arc::new(SolverCache::new(CACHE_CAPACITY));
+    let (result_tx, result_rx) = crossbeam::channel::bounded(16);
+    let threads = std::thread::available_parallelism()?.get();
+    let mut tasks = Vec::new();
+    for _ in 0..threads {
+        let query_rx = query_rx.clone();
+        let result_tx = result_tx.clone();
+        let title_map = title_map.clone();
+        let outgoing_links = outgoing_links.clone();
+        let incoming_links = incoming_links.clone();
+        let out_degree = out_degree.clone();
+        let in_degree = in_degree.clone();
+        let redirects = redirects.clone();
+        let cache = Arc::clone(&cache);
+        tasks.push(std::thread::spawn::<_, anyhow::Result<()>>(move || {
+            while let Ok((line, query)) = query_rx.recv() {
+                let mut record = PathRecord {
+                    line,
+                    start: String::new(),
+                    end: String::new(),
+                    degree: None,
+                    path: None,
+                    error: None,
+                };
+                match query.split_once('\t') {
+                    Some((start, end)) => {
+                        record.start = start.to_owned();
+                        record.end = end.to_owned();
+                        match (title_map.get_id(start), title_map.get_id(end)) {
+                            (Some(start), Some(end)) => {
+                                let start = resolve_link(redirects.as_ref(), start);
+                                let end = resolve_link(redirects.as_ref(), end);
+                                let mut solver = cache
+                                    .solver(start, end)
+                                    .with_degrees(out_degree.clone(), in_degree.clone());
+                                let path =
+                                    solver.solve(&outgoing_links, &incoming_links, None, false);
+                                cache.record(&solver);
+                                match path {
+                                    Some(path) => match path
+                                        .iter()
+                                        .map(|id| title_map.get_title(*id).ok_or(*id))
+                                        .collect::<Result<Vec<_>, _>>()
+                                    {
+                                        Ok(titles) => {
+                                            record.degree = Some(path.len() - 1);
+                                            record.path = Some(titles);
+                                        }
+                                        Err(id) => {
+                                            record.error =
+                                                Some(format!("article with ID {id} has no title"))
+                                        }
+                                    },
+                                    None => record.error = Some("no path exists".to_owned()),
+                                }
+                            }
+                            (None, _) => record.error = Some(format!("article {start} not found")),
+                            (_, None) => record.error = Some(format!("article {end} not found")),
+                        }
+                    }
+                    None => {
+                        record.error = Some("expected a line of the form start<TAB>end".to_owned())
+                    }
+                }
+                result_tx.send(record)?;
+            }
+            Ok(())
+        }));
+    }
+    drop(result_tx);
+
+    let mut writer = BufWriter::new(std::fs::File::create(output)?);
+    let mut errors = 0;
+    while let Ok(record) = result_rx.recv() {
+        if record.error.is_some() {
+            errors += 1;
+        }
+        serde_json::to_writer(&mut writer, &record)?;
+        writeln!(writer)?;
+        progress_bar.inc(1);
+    }
+    writer.flush()?;
+    progress_bar.finish();
+
+    // If a worker failed, the queries it would have solved are missing from the output.
+    for task in tasks {
+        task.join().map_err(|_| anyhow::Error::msg("panic"))??;
+    }
+
+    println!(
+        "Solved {} queries with {errors} errors",
+        progress_bar.position()
+    );
+
+    Ok(())
+}
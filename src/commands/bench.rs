@@ -0,0 +1,60 @@
+use std::time::Instant;
+
+use console::style;
+
+use crate::{
+    page::{page_stream, StopCondition},
+    parse::wikitext::find_links,
+};
+
+/// Parses the given number of pages and finds their links, reporting the throughput of the parsers.
+/// Nothing is cached, so that repeated runs measure the same work.
+/// With `main_namespace_only`, only pages in the main namespace are counted,
+/// which shows the speedup from skipping blocks of the dump that contain none.
+pub fn execute(articles: u64, main_namespace_only: bool) -> anyhow::Result<()> {
+    let start = Instant::now();
+    let stream = page_stream(
+        StopCondition::Count(articles),
+        64,
+        main_namespace_only,
+        "Parsing pages".to_owned(),
+        |page| {
+            (
+                page.revision.text.len(),
+                find_links(&page.revision.text).len(),
+            )
+        },
+    )?;
+
+    let mut pages = 0u64;
+    let mut links = 0u64;
+    let mut bytes = 0u64;
+    for (text_len, link_count) in stream {
+        pages += 1;
+        links += link_count as u64;
+        bytes += text_len as u64;
+    }
+    let seconds = start.elapsed().as_secs_f64();
+
+    println!(
+        "Parsed {} pages with {} links and {:.1} MB of text in {:.2}s",
+        style(pages).bold().bright(),
+        style(links).bold().bright(),
+        bytes as f64 / 1_000_000.0,
+        seconds
+    );
+    println!(
+        "{} pages/s, {} links/s, {} MB/s",
+        style(format!("{:.0}", pages as f64 / seconds))
+            .bold()
+            .bright(),
+        style(format!("{:.0}", links as f64 / seconds))
+            .bold()
+            .bright(),
+        style(format!("{:.2}", bytes as f64 / 1_000_000.0 / seconds))
+            .bold()
+            .bright()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use std::fs::File;
+
+use console::style;
+
+use crate::{
+    data_dir::data_dir,
+    page::{get_dump_status, read_pages, ParsedPage},
+    parse::xml::{make_errors_static, parse_element, parse_whitespace},
+};
+
+/// Lists the pages in the compressed block starting at the given byte offset of an articles file.
+/// If `file` is not given, we read the first articles file of the dump.
+pub fn execute(offset: u64, file: Option<String>) -> anyhow::Result<()> {
+    let dump_status = get_dump_status()?;
+    let files = dump_status.jobs.articles_multistream_dump.files();
+    let (name, articles) = files
+        .iter()
+        .filter(|(name, _)| !name.contains("index"))
+        .find(|(name, _)| file.as_ref().is_none_or(|file| file == name))
+        .ok_or_else(|| {
+            anyhow::Error::msg(format!(
+                "articles file {} not found",
+                file.as_deref().unwrap_or_default()
+            ))
+        })?;
+
+    let mut articles_file = File::open(data_dir().join(&articles.url))?;
+    let len = articles_file.metadata()?.len();
+    if offset >= len {
+        return Err(anyhow::Error::msg(format!(
+            "offset {offset} is past the end of {name}, which is {len} bytes long"
+        )));
+    }
+
+    let pages = read_pages(&mut articles_file, offset).map_err(|err| {
+        anyhow::Error::msg(format!(
+            "could not decompress a block at offset {offset} of {name}; \
+            offsets must be taken from the index file ({err})"
+        ))
+    })?;
+
+    let mut input = pages.as_str();
+    // The first block of each articles file holds the `<siteinfo>` prologue instead of any pages,
+    // and the last block ends by closing the root element.
+    if let Some(siteinfo_end) = input.find("</siteinfo>") {
+        println!("This block contains the siteinfo prologue");
+        input = &input[siteinfo_end + "</siteinfo>".len()..];
+    }
+    let input_trimmed = input.trim_end();
+    input = input_trimmed
+        .strip_suffix("</mediawiki>")
+        .unwrap_or(input_trimmed);
+
+    let mut count = 0;
+    loop {
+        let (new_input, _) = make_errors_static(parse_whitespace(input))?;
+        if new_input.is_empty() {
+            break;
+        }
+        let (new_input, page) = make_errors_static(parse_element(new_input))?;
+        input = new_input;
+        let page = ParsedPage::try_from(page)?;
+        println!(
+            "{:>10} {}",
+            style(page.id).dim(),
+            style(page.title).bold().bright()
+        );
+        count += 1;
+    }
+
+    println!(
+        "The block at offset {} of {name} held {} pages",
+        style(offset).bold().bright(),
+        style(count).bold().bright()
+    );
+
+    Ok(())
+}
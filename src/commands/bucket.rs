@@ -0,0 +1,102 @@
+use std::{fmt::Display, path::PathBuf, str::FromStr};
+
+use console::style;
+
+use crate::{
+    commands::links::new_link_map,
+    hierarchical_map::HierarchicalMap,
+    titles::{id_short_key, title_short_key, TitleMap},
+};
+
+/// The maps that can be inspected with this command.
+const KNOWN_MAPS: [&str; 5] = [
+    "id_to_title",
+    "title_to_id",
+    "outgoing_links",
+    "incoming_links",
+    "redirects",
+];
+
+/// Prints the contents of a single bucket of one of the cached hierarchical maps.
+pub fn execute(map: String, short_key: String) -> anyhow::Result<()> {
+    let title_map = TitleMap::default();
+    let title = |id: &u32| {
+        title_map
+            .get_title(*id)
+            .unwrap_or_else(|| format!("<id {id}>"))
+    };
+
+    match map.as_str() {
+        "id_to_title" => print_bucket(
+            HierarchicalMap::new(PathBuf::from(&map), id_short_key),
+            parse_short_key(&short_key)?,
+            |id, title: &String| format!("{id} {title}"),
+        ),
+        "title_to_id" => print_bucket(
+            HierarchicalMap::new(PathBuf::from(&map), |title: &String| title_short_key(title)),
+            short_key,
+            |title, id: &u32| format!("{title} {id}"),
+        ),
+        "outgoing_links" | "incoming_links" => {
+            let arrow = if map == "outgoing_links" { ">" } else { "<" };
+            print_bucket(
+                new_link_map(PathBuf::from(&map)),
+                parse_short_key(&short_key)?,
+                |id, links: &Vec<u32>| {
+                    let mut output = format!("{id} {}", style(title(id)).bold());
+                    for link in links {
+                        output.push_str(&format!("\n  {arrow} {}", title(link)));
+                    }
+                    output
+                },
+            )
+        }
+        "redirects" => print_bucket(
+            HierarchicalMap::new(PathBuf::from(&map), id_short_key),
+            parse_short_key(&short_key)?,
+            |id, target: &Option<u32>| match target {
+                Some(target) => format!("{id} {} → {}", title(id), title(target)),
+                None => format!("{id} {} → (dangling)", title(id)),
+            },
+        ),
+        _ => Err(anyhow::Error::msg(format!(
+            "unknown map {map}; expected one of {}",
+            KNOWN_MAPS.join(", ")
+        ))),
+    }
+}
+
+fn parse_short_key<K: FromStr>(short_key: &str) -> anyhow::Result<K> {
+    short_key
+        .parse()
+        .map_err(|_| anyhow::Error::msg(format!("invalid short key {short_key}")))
+}
+
+fn print_bucket<K, L, V>(
+    map: HierarchicalMap<K, L, V>,
+    short_key: K,
+    describe: impl Fn(&L, &V) -> String,
+) -> anyhow::Result<()>
+where
+    K: Ord + Display + Clone,
+    L: for<'a> serde::Deserialize<'a> + Ord,
+    V: for<'a> serde::Deserialize<'a>,
+{
+    if !map.deserialize_bucket(short_key.clone())? {
+        return Err(anyhow::Error::msg(format!(
+            "no bucket with short key {short_key} was found"
+        )));
+    }
+
+    let outer_map = map.get_map().read().unwrap();
+    let inner_map = outer_map[&short_key].read().unwrap();
+    for (key, value) in inner_map.iter() {
+        println!("{}", describe(key, value));
+    }
+    println!(
+        "\nBucket {short_key} contains {} entries",
+        style(inner_map.len()).bold().bright()
+    );
+
+    Ok(())
+}
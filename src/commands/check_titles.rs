@@ -0,0 +1,25 @@
+use console::style;
+
+use crate::titles::generate_title_map;
+
+/// Checks that the two halves of the title map agree with each other, listing any discrepancies.
+/// If `repair` is true and there are discrepancies, we rebuild the title to ID map from the ID to title map.
+pub fn execute(repair: bool) -> anyhow::Result<()> {
+    let title_map = generate_title_map(true)?;
+    let discrepancies = title_map.verify()?;
+
+    for discrepancy in &discrepancies {
+        println!("{discrepancy}");
+    }
+    println!(
+        "Found {} discrepancies in the title map",
+        style(discrepancies.len()).bold().bright()
+    );
+
+    if repair && !discrepancies.is_empty() {
+        title_map.repair()?;
+        println!("Rebuilt the title to ID map from the ID to title map");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use std::collections::BTreeMap;
+
+use console::style;
+
+use crate::stats::StreamingHistogram;
+
+use super::{links::generate_out_degree, word_count::generate_word_counts};
+
+/// Reports how the number of outgoing links from an article relates to its length in words.
+pub fn execute() -> anyhow::Result<()> {
+    let word_counts = generate_word_counts(true)?;
+    let out_degree = generate_out_degree(true)?;
+
+    // Articles are binned by the base-2 logarithm of their word count.
+    let mut bins = BTreeMap::<u32, StreamingHistogram>::new();
+    let mut correlation = Correlation::default();
+
+    let rx = word_counts.with_all(
+        "Joining word counts and out-degrees".to_owned(),
+        |id, words| (*id, *words),
+    );
+    while let Ok((id, words)) = rx.recv() {
+        let degree = out_degree.with(&id, |degree| *degree).unwrap_or(0);
+        correlation.add(words as f64, degree as f64);
+        bins.entry(words.checked_ilog2().map_or(0, |bin| bin + 1))
+            .or_default()
+            .add(degree as u64);
+    }
+
+    println!(
+        "{:>17} {:>10} {:>10} {:>10} {:>14}",
+        "words", "articles", "mean", "median", "links/1k words"
+    );
+    for (bin, histogram) in bins {
+        let (low, high) = match bin {
+            0 => (0, 0),
+            bin => (1u64 << (bin - 1), (1u64 << bin) - 1),
+        };
+        let midpoint = (low + high) as f64 / 2.0;
+        println!(
+            "{:>17} {:>10} {:>10.1} {:>10} {:>14}",
+            format!("{low}-{high}"),
+            histogram.count(),
+            histogram.mean(),
+            histogram.quantile(0.5),
+            if midpoint > 0.0 {
+                format!("{:.1}", histogram.mean() / midpoint * 1000.0)
+            } else {
+                "-".to_owned()
+            }
+        );
+    }
+
+    println!(
+        "\nCorrelation between word count and out-degree over {} articles: {}",
+        style(correlation.count).bold().bright(),
+        style(format!("{:.4}", correlation.pearson()))
+            .bold()
+            .bright()
+    );
+
+    Ok(())
+}
+
+/// Accumulates the sums needed to compute the Pearson correlation coefficient in a single pass.
+#[derive(Debug, Default)]
+struct Correlation {
+    count: u64,
+    sum_x: f64,
+    sum_y: f64,
+    sum_xx: f64,
+    sum_yy: f64,
+    sum_xy: f64,
+}
+
+impl Correlation {
+    fn add(&mut self, x: f64, y: f64) {
+        self.count += 1;
+        self.sum_x += x;
+        self.sum_y += y;
+        self.sum_xx += x * x;
+        self.sum_yy += y * y;
+        self.sum_xy += x * y;
+    }
+
+    fn pearson(&self) -> f64 {
+        let n = self.count as f64;
+        let covariance = n * self.sum_xy - self.sum_x * self.sum_y;
+        let variance_x = n * self.sum_xx - self.sum_x * self.sum_x;
+        let variance_y = n * self.sum_yy - self.sum_y * self.sum_y;
+        covariance / (variance_x * variance_y).sqrt()
+    }
+}
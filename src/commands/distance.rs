@@ -0,0 +1,27 @@
+use std::collections::HashSet;
+
+use crate::titles::generate_title_map;
+
+use super::shortest_path::{find_path, SearchStrategy};
+
+/// Prints the degree of separation between two articles as a single number, for use in scripts.
+/// If no path exists, we print nothing and exit with a non-zero status.
+pub fn execute(start: String, end: String) -> anyhow::Result<()> {
+    let title_map = generate_title_map(false)?;
+    let start = title_map.resolve(&start, false)?;
+    let end = title_map.resolve(&end, false)?;
+
+    match distance(start, end)? {
+        Some(distance) => {
+            println!("{distance}");
+            Ok(())
+        }
+        None => std::process::exit(1),
+    }
+}
+
+/// Returns the length of a minimal path between the two articles, if one exists.
+pub fn distance(start: u32, end: u32) -> anyhow::Result<Option<usize>> {
+    let (_, path) = find_path(start, end, SearchStrategy::default(), HashSet::new(), false)?;
+    Ok(path.map(|path| path.len() - 1))
+}
@@ -1,31 +1,76 @@
 use std::{
-    collections::BTreeMap,
-    io::{BufReader, BufWriter, Read, Write},
-    path::PathBuf,
-    str::FromStr,
+    collections::{BTreeMap, BTreeSet},
+    io::{BufReader, Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
     time::Duration,
 };
 
-use bzip2::bufread::BzDecoder;
+use bzip2::bufread::MultiBzDecoder;
 use chrono::{DateTime, Utc};
 use console::style;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use indicatif::{HumanBytes, MultiProgress};
 use serde::{Deserialize, Serialize};
-use ureq::{Agent, AgentBuilder};
+use sha1::{Digest, Sha1};
+use ureq::{Agent, AgentBuilder, Response};
+
+use crate::{
+    atomic_write::write_atomic,
+    data_dir::data_dir,
+    progress_bar::{file_progress_bar, message_spinner, step_progress_bar, ProgressHandle},
+};
 
-use crate::progress_bar::file_progress_bar;
+/// The file in the data directory recording the dump that it is pinned to, if any.
+const PIN_FILE: &str = ".pinned";
+/// The file in the data directory recording which files of the current dump were downloaded and verified.
+const MANIFEST_FILE: &str = "download_manifest.json";
 
 /// Executes the download command.
-pub fn execute(date: Option<String>) -> anyhow::Result<()> {
-    let spinner = ProgressBar::new_spinner()
-        .with_style(ProgressStyle::with_template("{spinner:.green} {wide_msg}").unwrap());
-    spinner.enable_steady_tick(Duration::from_millis(100));
+/// If `redirect_table` is true, we also download the SQL dump of the redirect table.
+///
+/// If the data directory is pinned to a dump, we resume downloading that dump,
+/// and refuse to switch to another dump unless `force` is true.
+/// If `pin` is true, the data directory is pinned to the dump we download.
+/// Up to `jobs` files are downloaded at once, and failed requests are retried up to `retries` times.
+/// Unless `skip_space_check` is true, we refuse to start if the data directory's filesystem is too small.
+pub fn execute(
+    date: Option<String>,
+    redirect_table: bool,
+    pin: bool,
+    force: bool,
+    jobs: u32,
+    retries: u32,
+    skip_space_check: bool,
+) -> anyhow::Result<()> {
+    let date = match pinned_dump()? {
+        Some(pinned) if force => {
+            std::fs::remove_file(data_dir().join(PIN_FILE))?;
+            println!("Unpinned version {}", style(pinned).bright().bold());
+            date
+        }
+        Some(pinned) => match date {
+            Some(date) if date != pinned => {
+                return Err(anyhow::Error::msg(format!(
+                    "the data directory is pinned to version {pinned}, \
+                    so downloading version {date} would mix it with existing caches; \
+                    pass --force to switch versions anyway"
+                )));
+            }
+            _ => {
+                println!("Resuming pinned version {}", style(&pinned).bright().bold());
+                Some(pinned)
+            }
+        },
+        None => date,
+    };
 
+    let spinner = message_spinner();
     spinner.set_message("Downloading dumps list");
 
-    let agent = AgentBuilder::new()
-        .user_agent("wiki-scraper-zeramorphic")
-        .build();
+    let client = Client::new(retries);
 
     match date {
         Some(date) => {
@@ -33,53 +78,41 @@ pub fn execute(date: Option<String>) -> anyhow::Result<()> {
                 "Downloading dump information for version {}",
                 style(&date).bright().bold()
             ));
-            let response = agent
-                .get(&format!(
-                    "https://dumps.wikimedia.org/enwiki/{date}/dumpstatus.json"
-                ))
-                .call()?;
-            let text = response.into_string()?;
-            let mut dump_status = serde_json::from_str::<DumpStatus>(&text)?;
-            dump_status.fix_paths();
-            dump_status.date = Some(date.clone());
+            let dump_status = client.dump_status(&date)?;
 
             assert!(dump_status.jobs.done());
             spinner.finish_with_message(format!("Using version {}", style(date).bright().bold()));
-            execute_dump(&agent, dump_status)
+            execute_dump(
+                &client,
+                dump_status,
+                redirect_table,
+                pin,
+                jobs,
+                skip_space_check,
+            )
         }
         None => {
-            // Obtain a list of the most recent available file dumps, e.g.
-            // ["20240301/", "20240320/", "20240401/", "20240420/", "20240501/", "20240601/", "20240620/", "latest/"]
-            let response = agent.get("https://dumps.wikimedia.org/enwiki/").call()?;
-            let file_names = crate::parse::parse_html_index::file_names(&response.into_string()?)?;
-
             // Iterate through the dumps in reverse order until we find a dump that's already finished.
             // This way we're always looking at the most recent completed dump.
-            for dir in file_names.into_iter().rev() {
-                let dir = dir.trim_end_matches('/');
-                if dir.contains("latest") {
-                    continue;
-                }
+            for version in client.dump_versions()?.into_iter().rev() {
                 spinner.set_message(format!(
                     "Downloading dump information for version {}",
-                    style(dir).bright().bold()
+                    style(&version).bright().bold()
                 ));
-                let response = agent
-                    .get(&format!(
-                        "https://dumps.wikimedia.org/enwiki/{dir}/dumpstatus.json"
-                    ))
-                    .call()?;
-                let text = response.into_string()?;
-                let mut dump_status = serde_json::from_str::<DumpStatus>(&text)?;
-                dump_status.fix_paths();
-                dump_status.date = Some(dir.to_owned());
-
+                let dump_status = client.dump_status(&version)?;
                 if dump_status.jobs.done() {
                     spinner.finish_with_message(format!(
                         "Using version {}",
-                        style(dir).bright().bold()
+                        style(version).bright().bold()
                     ));
-                    return execute_dump(&agent, dump_status);
+                    return execute_dump(
+                        &client,
+                        dump_status,
+                        redirect_table,
+                        pin,
+                        jobs,
+                        skip_space_check,
+                    );
                 }
             }
 
@@ -88,84 +121,483 @@ pub fn execute(date: Option<String>) -> anyhow::Result<()> {
     }
 }
 
+/// Returns the version of the dump that the data directory is pinned to, if any.
+fn pinned_dump() -> anyhow::Result<Option<String>> {
+    match std::fs::read_to_string(data_dir().join(PIN_FILE)) {
+        Ok(date) => Ok(Some(date.trim().to_owned())),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
 /// Download this completed dump.
-fn execute_dump(agent: &Agent, dump_status: DumpStatus) -> anyhow::Result<()> {
-    std::fs::create_dir_all("data")?;
-    std::fs::write(
-        "data/current_dump.json",
-        serde_json::to_string_pretty(&dump_status)?,
-    )?;
+fn execute_dump(
+    client: &Client,
+    mut dump_status: DumpStatus,
+    redirect_table: bool,
+    pin: bool,
+    jobs: u32,
+    skip_space_check: bool,
+) -> anyhow::Result<()> {
+    if !redirect_table {
+        // Don't record the redirect table in `current_dump.json` if we're not going to download it.
+        dump_status.jobs.redirect_table = None;
+    }
 
-    let multi_progress = MultiProgress::new();
+    std::fs::create_dir_all(data_dir())?;
+    if pin {
+        if let Some(date) = &dump_status.date {
+            write_atomic(&data_dir().join(PIN_FILE), |writer| {
+                Ok(writer.write_all(date.as_bytes())?)
+            })?;
+        }
+    }
+    write_atomic(&data_dir().join("current_dump.json"), |writer| {
+        Ok(serde_json::to_writer_pretty(writer, &dump_status)?)
+    })?;
+
+    // Files are only recorded in the manifest once they have been downloaded and verified,
+    // so any other file on disk may be truncated and must be downloaded again.
+    // Data directories from before manifests were introduced have no manifest,
+    // so in that case we trust the files that are already there.
+    let (manifest, trust_existing) = match DownloadManifest::load()? {
+        Some(manifest) if manifest.date == dump_status.date => (manifest, false),
+        Some(_) => (DownloadManifest::new(dump_status.date.clone()), false),
+        None => (DownloadManifest::new(dump_status.date.clone()), true),
+    };
+    manifest.save()?;
 
     let all_files = dump_status.jobs.all_files();
 
-    let main_progress = ProgressBar::new(all_files.len() as u64)
-        .with_style(ProgressStyle::with_template("[{pos}/{len}] {wide_msg}").unwrap());
-    multi_progress.add(main_progress.clone());
+    if !skip_space_check {
+        let required = all_files
+            .iter()
+            .filter(|(file, status)| {
+                let downloaded = manifest.completed.contains(file)
+                    || (trust_existing && local_path(status).is_file());
+                !downloaded
+            })
+            .map(|(_, status)| required_space(status))
+            .sum::<u64>();
+        let available = fs2::available_space(data_dir())?;
+        if required > available {
+            return Err(anyhow::Error::msg(format!(
+                "the download needs about {} of disk space, but only {} is available in {}; \
+                free up some space, or pass --skip-space-check to download anyway",
+                HumanBytes(required),
+                HumanBytes(available),
+                data_dir().display()
+            )));
+        }
+    }
+
+    let multi_progress = MultiProgress::new();
+    let main_progress = step_progress_bar(all_files.len() as u64).with_message("Downloading files");
+    multi_progress.add(main_progress.terminal().clone());
 
+    let (file_tx, file_rx) = crossbeam::channel::unbounded();
     for (file, status) in all_files {
-        main_progress.set_message(format!("Downloading {file}"));
-        let file_progress = file_progress_bar(status.size);
-        download_file(agent, &status, &file_progress)?;
-        main_progress.inc(1);
-        multi_progress.remove(&file_progress);
+        if manifest.completed.contains(&file) {
+            main_progress.inc(1);
+        } else {
+            file_tx.send((file, status))?;
+        }
     }
+    drop(file_tx);
+
+    // If any download fails, we cancel the others, so that we report the failure promptly
+    // rather than after every other file has downloaded.
+    let cancelled = AtomicBool::new(false);
+    let first_error = Mutex::new(None);
+    let manifest = Mutex::new(manifest);
+    std::thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| {
+                while let Ok((file, status)) = file_rx.recv() {
+                    if cancelled.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    let local_path = local_path(&status);
+                    if !(trust_existing && local_path.is_file()) {
+                        let file_progress =
+                            file_progress_bar(status.size).with_message(file.clone());
+                        multi_progress.add(file_progress.terminal().clone());
+                        let result =
+                            download_file(client, &status, &local_path, &file_progress, &cancelled);
+                        multi_progress.remove(file_progress.terminal());
+                        if let Err(err) = result {
+                            // Only the first failure is interesting: the rest are caused by the cancellation.
+                            if !cancelled.swap(true, Ordering::SeqCst) {
+                                *first_error.lock().unwrap() = Some(anyhow::Error::msg(format!(
+                                    "could not download {file}: {err}"
+                                )));
+                            }
+                            break;
+                        }
+                    }
+                    let mut manifest = manifest.lock().unwrap();
+                    manifest.completed.insert(file);
+                    if let Err(err) = manifest.save() {
+                        if !cancelled.swap(true, Ordering::SeqCst) {
+                            *first_error.lock().unwrap() = Some(err);
+                        }
+                        break;
+                    }
+                    main_progress.inc(1);
+                }
+            });
+        }
+    });
 
-    main_progress.finish();
+    match first_error.into_inner().unwrap() {
+        None => {
+            main_progress.finish_with_message("Downloaded all files");
+            Ok(())
+        }
+        Some(err) => {
+            main_progress.finish_with_message("Download cancelled");
+            Err(anyhow::Error::msg(format!(
+                "{err}; run the download command again to resume the download"
+            )))
+        }
+    }
+}
+
+/// The default number of times to retry a failed request.
+pub const DEFAULT_RETRIES: u32 = 5;
+
+/// An HTTP agent that retries requests that fail for reasons that are likely to be transient.
+pub struct Client {
+    agent: Agent,
+    retries: u32,
+}
+
+impl Client {
+    /// Creates a client that retries each failed request up to `retries` times.
+    pub fn new(retries: u32) -> Self {
+        Self {
+            agent: AgentBuilder::new()
+                .user_agent("wiki-scraper-zeramorphic")
+                .build(),
+            retries,
+        }
+    }
+
+    /// Lists the versions of the dump that are available, from oldest to newest, such as `20240601`.
+    pub fn dump_versions(&self) -> anyhow::Result<Vec<String>> {
+        // Obtain a list of the most recent available file dumps, e.g.
+        // ["20240301/", "20240320/", "20240401/", "20240420/", "20240501/", "20240601/", "20240620/", "latest/"]
+        let response = self.get("https://dumps.wikimedia.org/enwiki/")?;
+        let file_names = crate::parse::parse_html_index::file_names(&response.into_string()?)?;
+        Ok(file_names
+            .into_iter()
+            .map(|dir| dir.trim_end_matches('/').to_owned())
+            .filter(|dir| !dir.contains("latest"))
+            .collect())
+    }
+
+    /// Downloads the status of the given version of the dump.
+    pub fn dump_status(&self, version: &str) -> anyhow::Result<DumpStatus> {
+        let response = self.get(&format!(
+            "https://dumps.wikimedia.org/enwiki/{version}/dumpstatus.json"
+        ))?;
+        let mut dump_status = serde_json::from_str::<DumpStatus>(&response.into_string()?)?;
+        dump_status.fix_paths();
+        dump_status.date = Some(version.to_owned());
+        Ok(dump_status)
+    }
+
+    /// Sends a GET request to the given URL.
+    fn get(&self, url: &str) -> anyhow::Result<Response> {
+        self.get_from(url, 0, &AtomicBool::new(false))
+    }
+
+    /// Sends a GET request for the bytes of the given URL from `offset` onwards.
+    /// If the connection fails or the server reports an error (5xx), we retry with exponential backoff,
+    /// waiting one second before the first retry and doubling the wait each time.
+    /// Other errors, such as 404 Not Found, are returned immediately,
+    /// as is an error if the download is cancelled while we wait to retry.
+    fn get_from(&self, url: &str, offset: u64, cancelled: &AtomicBool) -> anyhow::Result<Response> {
+        let mut delay = Duration::from_secs(1);
+        let mut attempt = 0;
+        loop {
+            let mut request = self.agent.get(url);
+            if offset > 0 {
+                request = request.set("Range", &format!("bytes={offset}-"));
+            }
+            match request.call() {
+                // A server that ignores the range would send the whole file again.
+                Ok(response) if offset > 0 && response.status() != 206 => {
+                    return Err(anyhow::Error::msg(format!(
+                        "server does not support resuming the download of {url}"
+                    )))
+                }
+                Ok(response) => return Ok(response),
+                Err(err) => {
+                    let transient = match &err {
+                        ureq::Error::Status(code, _) => *code >= 500,
+                        ureq::Error::Transport(_) => true,
+                    };
+                    if !transient || attempt >= self.retries {
+                        return Err(err.into());
+                    }
+                }
+            }
+            attempt += 1;
+            sleep_unless_cancelled(delay, cancelled)?;
+            delay *= 2;
+        }
+    }
+}
 
+/// Sleeps for the given duration, failing early if the download is cancelled in the meantime.
+fn sleep_unless_cancelled(duration: Duration, cancelled: &AtomicBool) -> anyhow::Result<()> {
+    const STEP: Duration = Duration::from_millis(100);
+    let mut remaining = duration;
+    while !remaining.is_zero() {
+        if cancelled.load(Ordering::SeqCst) {
+            return Err(anyhow::Error::msg("download cancelled"));
+        }
+        let step = remaining.min(STEP);
+        std::thread::sleep(step);
+        remaining -= step;
+    }
     Ok(())
 }
 
-fn download_file(agent: &Agent, status: &FileStatus, progress: &ProgressBar) -> anyhow::Result<()> {
-    // Special case: BZ2-decompress index files.
-    let is_index = status.url.contains("index");
+/// Reads the body of a download, resuming it from the last byte read if the connection drops,
+/// up to the client's number of retries.
+struct ResumingReader<'a> {
+    client: &'a Client,
+    url: &'a str,
+    cancelled: &'a AtomicBool,
+    inner: Box<dyn Read + Send + Sync>,
+    /// The length of the whole body, which tells us whether a body that ended early was cut off.
+    len: u64,
+    /// The number of bytes of the body read so far.
+    position: u64,
+    resumes: u32,
+}
 
-    let mut local_path = PathBuf::from_str("data").unwrap().join(&status.url);
-    if is_index {
+impl Read for ResumingReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let err = match self.inner.read(buf) {
+                Ok(0) if !buf.is_empty() && self.position < self.len => std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "connection closed before the download finished",
+                ),
+                Ok(bytes_read) => {
+                    self.position += bytes_read as u64;
+                    return Ok(bytes_read);
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(err) => err,
+            };
+            if self.resumes >= self.client.retries {
+                return Err(err);
+            }
+            self.resumes += 1;
+            self.inner = self
+                .client
+                .get_from(self.url, self.position, self.cancelled)
+                .map_err(std::io::Error::other)?
+                .into_reader();
+        }
+    }
+}
+
+/// The path at which the given file is stored in the data directory.
+/// Index files are stored decompressed.
+pub fn local_path(status: &FileStatus) -> PathBuf {
+    let mut local_path = data_dir().join(&status.url);
+    if is_index(status) {
         local_path.set_extension("txt");
     };
-    if std::fs::metadata(&local_path).is_ok_and(|metadata| metadata.is_file()) {
-        // We already downloaded the file; exit early.
-        return Ok(());
+    local_path
+}
+
+/// Index files are decompressed to roughly this many times their compressed size.
+const INDEX_EXPANSION: u64 = 5;
+
+/// An estimate of the disk space needed to store the given file once it has been downloaded.
+/// While a file is being downloaded, it is written to a temporary file, so no extra space is needed.
+fn required_space(status: &FileStatus) -> u64 {
+    if is_index(status) {
+        status.size * INDEX_EXPANSION
+    } else {
+        status.size
     }
+}
+
+/// Index files are BZ2-decompressed when they are downloaded.
+pub fn is_index(status: &FileStatus) -> bool {
+    status.url.contains("index")
+}
+
+/// Downloads the file to the given path, failing if the download is cancelled
+/// or the file does not match its published hashes.
+/// The file is only moved into place once it has been verified,
+/// so an interrupted download never leaves a truncated file at the local path.
+fn download_file(
+    client: &Client,
+    status: &FileStatus,
+    local_path: &Path,
+    progress: &ProgressHandle,
+    cancelled: &AtomicBool,
+) -> anyhow::Result<()> {
+    let is_index = is_index(status);
 
     let url = format!("https://dumps.wikimedia.org/{}", status.url);
-    let response = agent.get(&url).call()?;
+    let response = client.get_from(&url, 0, cancelled)?;
 
     // The response succeeded, so let's create the local file.
-    std::fs::create_dir_all(local_path.parent().unwrap())?;
-    let output = std::fs::File::create(local_path)?;
-    let mut writer = BufWriter::new(output);
-
-    let mut md5_context = md5::Context::new();
-    let mut reader: Box<dyn Read> = if is_index {
-        Box::new(BufReader::new(BzDecoder::new(BufReader::new(
-            response.into_reader(),
-        ))))
-    } else {
-        Box::new(BufReader::new(response.into_reader()))
-    };
-    let mut buf = vec![0u8; 0x10000];
-    loop {
-        let bytes_read = reader.read(&mut buf)?;
-        if bytes_read == 0 {
-            break;
+    write_atomic(local_path, |writer| {
+        // If the connection drops partway through the body, we carry on from where it stopped.
+        let body = ResumingReader {
+            client,
+            url: &url,
+            cancelled,
+            inner: response.into_reader(),
+            len: status.size,
+            position: 0,
+            resumes: 0,
+        };
+        // We hash the bytes exactly as they were downloaded, before any decompression,
+        // so that the digest can be compared against the published hashes even for index files.
+        let mut hashing_reader = HashingReader::new(body);
+        {
+            let mut reader: Box<dyn Read> = if is_index {
+                Box::new(BufReader::new(MultiBzDecoder::new(BufReader::new(
+                    &mut hashing_reader,
+                ))))
+            } else {
+                Box::new(BufReader::new(&mut hashing_reader))
+            };
+            let mut buf = vec![0u8; 0x10000];
+            loop {
+                if cancelled.load(Ordering::SeqCst) {
+                    return Err(anyhow::Error::msg("download cancelled"));
+                }
+                let bytes_read = reader.read(&mut buf)?;
+                if bytes_read == 0 {
+                    break;
+                }
+                progress.inc(bytes_read as u64);
+                writer.write_all(&buf[0..bytes_read])?;
+            }
+        }
+        // We decompress every stream in an index file, so a truncated index fails in the decompressor,
+        // but make sure that any bytes after the last stream are hashed too.
+        std::io::copy(&mut hashing_reader, &mut std::io::sink())?;
+
+        // Returning an error discards the temporary file, so a corrupt download never reaches the local path
+        // and the next attempt starts from scratch.
+        hashing_reader.verify(status, &url)
+    })
+}
+
+/// Records which files of a dump were downloaded and verified successfully.
+/// This lets an interrupted download resume with only the missing files,
+/// and lets other commands refuse to run on an incomplete download.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DownloadManifest {
+    /// The version of the dump that these files belong to.
+    pub date: Option<String>,
+    /// The names of the files that were downloaded and verified, as given in `dumpstatus.json`.
+    pub completed: BTreeSet<String>,
+}
+
+impl DownloadManifest {
+    fn new(date: Option<String>) -> Self {
+        Self {
+            date,
+            completed: BTreeSet::new(),
         }
-        progress.inc(bytes_read as u64);
-        writer.write_all(&buf[0..bytes_read])?;
-        md5_context.consume(&buf[0..bytes_read]);
     }
 
-    let digest = format!("{:x}", md5_context.compute());
-    if !is_index {
-        // For now we just ignore the MD5 hash of index files, because
-        // we're actually calculating the decompressed digest.
-        assert_eq!(status.md5, digest);
+    /// Reads the manifest of the current dump.
+    /// Returns `None` if there is no manifest, for instance if the data directory predates manifests.
+    pub fn load() -> anyhow::Result<Option<Self>> {
+        match std::fs::read_to_string(data_dir().join(MANIFEST_FILE)) {
+            Ok(text) => Ok(Some(serde_json::from_str(&text)?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
     }
 
-    writer.flush()?;
-    Ok(())
+    fn save(&self) -> anyhow::Result<()> {
+        write_atomic(&data_dir().join(MANIFEST_FILE), |writer| {
+            Ok(serde_json::to_writer_pretty(writer, self)?)
+        })
+    }
+
+    /// Returns the files of the given dump that were never downloaded and verified.
+    pub fn missing_files(&self, dump_status: &DumpStatus) -> Vec<String> {
+        dump_status
+            .jobs
+            .all_files()
+            .into_iter()
+            .map(|(file, _)| file)
+            .filter(|file| self.date != dump_status.date || !self.completed.contains(file))
+            .collect()
+    }
+}
+
+/// Computes the MD5 and SHA1 hashes of all of the data read through it.
+struct HashingReader<R> {
+    inner: R,
+    md5: md5::Context,
+    sha1: Sha1,
+}
+
+impl<R> HashingReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            md5: md5::Context::new(),
+            sha1: Sha1::new(),
+        }
+    }
+
+    /// Returns the MD5 and SHA1 hashes of the data read so far, as lowercase hexadecimal strings.
+    fn finish(self) -> (String, String) {
+        (
+            format!("{:x}", self.md5.compute()),
+            format!("{:x}", self.sha1.finalize()),
+        )
+    }
+
+    /// Checks the data read so far against the published hashes of the file downloaded from `url`.
+    fn verify(self, status: &FileStatus, url: &str) -> anyhow::Result<()> {
+        let (md5, sha1) = self.finish();
+        if status.md5 != md5 {
+            return Err(anyhow::Error::msg(format!(
+                "MD5 hash of {url} does not match the published hash: expected {}, got {md5}",
+                status.md5
+            )));
+        }
+        if let Some(published_sha1) = &status.sha1 {
+            if *published_sha1 != sha1 {
+                return Err(anyhow::Error::msg(format!(
+                    "SHA1 hash of {url} does not match the published hash: expected {published_sha1}, got {sha1}"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R> Read for HashingReader<R>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let result = self.inner.read(buf)?;
+        self.md5.consume(&buf[0..result]);
+        self.sha1.update(&buf[0..result]);
+        Ok(result)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -189,6 +621,10 @@ pub struct JobsStatus {
     pub all_page_titles_dump: JobStatus,
     #[serde(rename = "articlesmultistreamdump")]
     pub articles_multistream_dump: JobStatus,
+    /// The SQL dump of the redirect table.
+    /// This is optional, since it's only used to speed up computing the redirect map.
+    #[serde(rename = "redirecttable", default)]
+    pub redirect_table: Option<JobStatus>,
 }
 
 impl JobsStatus {
@@ -202,6 +638,9 @@ impl JobsStatus {
         self.site_stats.fix_paths();
         self.all_page_titles_dump.fix_paths();
         self.articles_multistream_dump.fix_paths();
+        if let Some(redirect_table) = &mut self.redirect_table {
+            redirect_table.fix_paths();
+        }
     }
 
     pub fn all_files(&self) -> Vec<(String, FileStatus)> {
@@ -209,6 +648,10 @@ impl JobsStatus {
             self.site_stats.files(),
             self.all_page_titles_dump.files(),
             self.articles_multistream_dump.files(),
+            self.redirect_table
+                .as_ref()
+                .map(JobStatus::files)
+                .unwrap_or_default(),
         ]
         .into_iter()
         .flatten()
@@ -258,6 +701,9 @@ pub struct FileStatus {
     pub size: u64,
     pub url: String,
     pub md5: String,
+    /// Not every dump publishes SHA1 hashes, so this is only checked if present.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha1: Option<String>,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -287,3 +733,59 @@ mod custom_date_format {
         Ok(DateTime::<Utc>::from_naive_utc_and_offset(dt, Utc))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The published hashes of the text `hello`.
+    fn hello_status() -> FileStatus {
+        FileStatus {
+            size: 5,
+            url: "hello.txt".to_owned(),
+            md5: "5d41402abc4b2a76b9719d911017c592".to_owned(),
+            sha1: Some("aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d".to_owned()),
+        }
+    }
+
+    fn hash_hello() -> HashingReader<&'static [u8]> {
+        let mut hashing_reader = HashingReader::new(&b"hello"[..]);
+        std::io::copy(&mut hashing_reader, &mut std::io::sink()).unwrap();
+        hashing_reader
+    }
+
+    #[test]
+    fn matching_hashes_are_accepted() {
+        hash_hello().verify(&hello_status(), "hello.txt").unwrap();
+    }
+
+    #[test]
+    fn wrong_checksums_discard_the_download() {
+        let mut status = hello_status();
+        status.md5 = "00000000000000000000000000000000".to_owned();
+        let dir = std::env::temp_dir().join(format!("wrong-checksum-{}", std::process::id()));
+        let path = dir.join("hello.txt");
+
+        let err = write_atomic(&path, |writer| {
+            writer.write_all(b"hello")?;
+            hash_hello().verify(&status, "https://example.org/hello.txt")
+        })
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "MD5 hash of https://example.org/hello.txt does not match the published hash: \
+            expected 00000000000000000000000000000000, got 5d41402abc4b2a76b9719d911017c592"
+        );
+        // Neither the file nor its temporary file is left behind, so the next attempt starts clean.
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn wrong_sha1_is_rejected() {
+        let mut status = hello_status();
+        status.sha1 = Some("0000000000000000000000000000000000000000".to_owned());
+        let err = hash_hello().verify(&status, "hello.txt").unwrap_err();
+        assert!(err.to_string().starts_with("SHA1 hash of hello.txt"));
+    }
+}
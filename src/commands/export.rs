@@ -0,0 +1,175 @@
+use std::{
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use clap::ValueEnum;
+use console::style;
+
+use crate::{commands::links::generate_outgoing_links, titles::generate_title_map};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    /// A SQLite database with tables `pages(id, title, namespace, is_redirect)` and `links(source_id, target_id)`
+    Sqlite,
+    /// A directory containing the link graph in compressed sparse row form, as little-endian binary arrays.
+    /// Articles are numbered `0..n` in increasing order of page ID.
+    /// `vertices.bin` holds the `n` page IDs as `u32`s, and `titles.txt` holds the `n` titles, one per line.
+    /// `offsets.bin` holds `n + 1` `u64`s, and the links from article `i` are the `u32` article numbers
+    /// between positions `offsets[i]` and `offsets[i + 1]` of `neighbors.bin`.
+    Csr,
+}
+
+pub fn execute(format: ExportFormat, output: &Path) -> anyhow::Result<()> {
+    match format {
+        ExportFormat::Sqlite => export_sqlite(output),
+        ExportFormat::Csr => export_csr(output),
+    }
+}
+
+fn export_csr(output: &Path) -> anyhow::Result<()> {
+    let title_map = generate_title_map(true)?;
+    let outgoing_links = generate_outgoing_links(true)?;
+
+    if output.exists() {
+        return Err(anyhow::Error::msg(format!(
+            "{} already exists",
+            output.display()
+        )));
+    }
+    std::fs::create_dir_all(output)?;
+
+    // Every article that links or is linked to gets a vertex, numbered in increasing order of page ID.
+    let mut vertices = Vec::new();
+    let rx = outgoing_links.with_all("Numbering articles".to_owned(), |id, links| {
+        (*id, links.clone())
+    });
+    while let Ok((id, links)) = rx.recv() {
+        vertices.push(id);
+        vertices.extend(links);
+    }
+    vertices.sort_unstable();
+    vertices.dedup();
+    let index = |id: &u32| vertices.binary_search(id).unwrap() as u32;
+
+    let mut vertices_writer = BufWriter::new(std::fs::File::create(output.join("vertices.bin"))?);
+    let mut titles_writer = BufWriter::new(std::fs::File::create(output.join("titles.txt"))?);
+    let mut offsets_writer = BufWriter::new(std::fs::File::create(output.join("offsets.bin"))?);
+    let mut neighbors_writer = BufWriter::new(std::fs::File::create(output.join("neighbors.bin"))?);
+
+    let mut offset = 0u64;
+    offsets_writer.write_all(&offset.to_le_bytes())?;
+    for id in &vertices {
+        vertices_writer.write_all(&id.to_le_bytes())?;
+        writeln!(
+            titles_writer,
+            "{}",
+            title_map.get_title(*id).unwrap_or_default()
+        )?;
+
+        let mut neighbors = outgoing_links
+            .with(id, |links| links.iter().map(index).collect::<Vec<_>>())
+            .unwrap_or_default();
+        neighbors.sort_unstable();
+        for neighbor in &neighbors {
+            neighbors_writer.write_all(&neighbor.to_le_bytes())?;
+        }
+        offset += neighbors.len() as u64;
+        offsets_writer.write_all(&offset.to_le_bytes())?;
+    }
+
+    vertices_writer.flush()?;
+    titles_writer.flush()?;
+    offsets_writer.flush()?;
+    neighbors_writer.flush()?;
+
+    println!(
+        "Exported {} articles and {} links to {}",
+        style(vertices.len()).bold().bright(),
+        style(offset).bold().bright(),
+        style(output.display()).bold().bright()
+    );
+
+    Ok(())
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn export_sqlite(_output: &Path) -> anyhow::Result<()> {
+    Err(anyhow::Error::msg(
+        "SQLite export is not supported by this build; rebuild with `--features sqlite`",
+    ))
+}
+
+#[cfg(feature = "sqlite")]
+fn export_sqlite(output: &Path) -> anyhow::Result<()> {
+    use rusqlite::{params, Connection};
+
+    use crate::{commands::redirects::generate_redirects, titles::split_namespace};
+
+    let title_map = generate_title_map(true)?;
+    let redirects = generate_redirects(true)?;
+    let outgoing_links = generate_outgoing_links(true)?;
+
+    if output.exists() {
+        return Err(anyhow::Error::msg(format!(
+            "{} already exists",
+            output.display()
+        )));
+    }
+    let mut connection = Connection::open(output)?;
+    connection.execute_batch(
+        "PRAGMA journal_mode = OFF;
+        PRAGMA synchronous = OFF;
+        CREATE TABLE pages (
+            id INTEGER PRIMARY KEY,
+            title TEXT NOT NULL,
+            namespace TEXT,
+            is_redirect INTEGER NOT NULL
+        );
+        CREATE TABLE links (
+            source_id INTEGER NOT NULL,
+            target_id INTEGER NOT NULL
+        );",
+    )?;
+
+    // Everything is inserted in a single transaction, since committing after every row is extremely slow.
+    let transaction = connection.transaction()?;
+    {
+        let mut insert_page = transaction.prepare(
+            "INSERT INTO pages (id, title, namespace, is_redirect) VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        let rx = title_map.with_all_titles("Exporting pages".to_owned());
+        while let Ok((id, title)) = rx.recv() {
+            let (namespace, _) = split_namespace(&title);
+            let is_redirect = redirects.contains_key(&id)?;
+            insert_page.execute(params![id, title, namespace, is_redirect])?;
+        }
+
+        let mut insert_link =
+            transaction.prepare("INSERT INTO links (source_id, target_id) VALUES (?1, ?2)")?;
+        let rx = outgoing_links.with_all("Exporting links".to_owned(), |id, links| {
+            (*id, links.clone())
+        });
+        while let Ok((id, links)) = rx.recv() {
+            for link in links {
+                insert_link.execute(params![id, link])?;
+            }
+        }
+    }
+    transaction.commit()?;
+
+    // Creating the indexes after inserting the data is much faster than maintaining them during insertion.
+    println!("Creating indexes");
+    connection.execute_batch(
+        "CREATE INDEX pages_title ON pages (title);
+        CREATE INDEX links_source ON links (source_id);
+        CREATE INDEX links_target ON links (target_id);",
+    )?;
+
+    println!(
+        "Exported link graph to {}",
+        style(output.display()).bold().bright()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,111 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use console::style;
+
+use crate::{
+    commands::{
+        random_article::random_article_id,
+        shortest_path::{SolverCache, ENDS_PER_START},
+    },
+    page::{get_dump_status, PageReader},
+    progress_bar::normal_progress_bar,
+    titles::generate_title_map,
+};
+
+use super::links::{generate_incoming_links, generate_outgoing_links};
+
+/// Finds the shortest paths between `samples` random pairs of articles,
+/// and lists the `top` articles that appear most often in the middle of these paths.
+pub fn execute(samples: u64, top: usize) -> anyhow::Result<()> {
+    let dump_status = get_dump_status()?;
+    println!("Loading title map");
+    let title_map = generate_title_map(true)?;
+    println!("Loading outgoing link map");
+    let outgoing_links = generate_outgoing_links(true)?;
+    println!("Loading incoming link map");
+    let incoming_links = generate_incoming_links(true)?;
+    println!("All data loaded.");
+
+    let progress_bar = normal_progress_bar(samples).with_message("Sampling paths");
+    // The number of samples claimed by a thread so far.
+    let claimed = Arc::new(AtomicU64::new(0));
+    let paths_found = Arc::new(AtomicU64::new(0));
+    // The number of times each article appears as an intermediate article of a path.
+    let counts = Arc::new(Mutex::new(HashMap::<u32, u64>::new()));
+
+    let threads = std::thread::available_parallelism()?.get();
+    // Each thread searches from one start article at a time.
+    let cache = Arc::new(SolverCache::new(threads));
+    let tasks = (0..threads)
+        .map(|_| {
+            let dump_status = dump_status.clone();
+            let title_map = title_map.clone();
+            let outgoing_links = outgoing_links.clone();
+            let incoming_links = incoming_links.clone();
+            let cache = cache.clone();
+            let progress_bar = progress_bar.clone();
+            let claimed = claimed.clone();
+            let paths_found = paths_found.clone();
+            let counts = counts.clone();
+            std::thread::spawn::<_, anyhow::Result<()>>(move || {
+                let mut page_reader = PageReader::new(&dump_status)?;
+                // Several end articles are sampled for each start article, so that the searches can share its levels.
+                let mut start = random_article_id(&mut page_reader, &title_map, true)?;
+                let mut ends_tried = 0;
+                while claimed.fetch_add(1, Ordering::SeqCst) < samples {
+                    if ends_tried == ENDS_PER_START {
+                        start = random_article_id(&mut page_reader, &title_map, true)?;
+                        ends_tried = 0;
+                    }
+                    ends_tried += 1;
+                    let end = random_article_id(&mut page_reader, &title_map, true)?;
+                    let mut solver = cache.solver(start, end);
+                    let path = solver.solve(&outgoing_links, &incoming_links, None, false);
+                    cache.record(&solver);
+                    if let Some(path) = path {
+                        paths_found.fetch_add(1, Ordering::SeqCst);
+                        if path.len() > 2 {
+                            let mut counts = counts.lock().unwrap();
+                            for id in &path[1..path.len() - 1] {
+                                *counts.entry(*id).or_default() += 1;
+                            }
+                        }
+                    }
+                    progress_bar.inc(1);
+                }
+                Ok(())
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for task in tasks {
+        task.join().map_err(|_| anyhow::Error::msg("panic"))??;
+    }
+    progress_bar.finish();
+
+    let paths_found = paths_found.load(Ordering::SeqCst);
+    let mut counts = counts.lock().unwrap().drain().collect::<Vec<_>>();
+    counts.sort_by_key(|(id, count)| (std::cmp::Reverse(*count), *id));
+
+    println!(
+        "Found {} paths between {samples} random pairs of articles; the most common intermediate articles are:",
+        style(paths_found).bold().bright()
+    );
+    for (i, (id, count)) in counts.into_iter().take(top).enumerate() {
+        println!(
+            "{:>5} {} ({} paths, {:.1}%)",
+            style(format!("{}.", i + 1)).dim(),
+            title_map.get_title(id).unwrap(),
+            style(count).bold().bright(),
+            100.0 * count as f64 / paths_found as f64
+        );
+    }
+
+    Ok(())
+}
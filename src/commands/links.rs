@@ -1,28 +1,153 @@
 use std::{
-    path::PathBuf,
+    collections::{hash_map::Entry, HashMap, HashSet},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicUsize, Ordering},
-        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, OnceLock,
     },
 };
 
+use console::style;
+
 use crate::{
+    commands::redirects::{generate_redirects, resolve_redirect, RedirectMap, RedirectResolution},
+    data_dir::data_dir,
     hierarchical_map::HierarchicalMap,
-    page::page_stream,
-    parse::wikitext::find_links,
-    titles::{
-        canonicalise_wikilink, generate_title_map, id_short_key, is_interwiki_link, split_namespace,
-    },
+    page::{page_stream, StopCondition},
+    parse::wikitext::{find_links, find_links_by_section},
+    progress_bar::normal_progress_bar,
+    titles::{generate_title_map, id_short_key, is_interwiki_link, split_namespace},
 };
 
 use itertools::Itertools;
 
-pub fn execute(article: String) -> anyhow::Result<()> {
+pub type LinkMap = HierarchicalMap<u8, u32, Vec<u32>>;
+pub type DegreeMap = HierarchicalMap<u8, u32, u32>;
+/// Maps each article to the articles it links to, together with the section of the article that contains each link.
+pub type SectionLinkMap = HierarchicalMap<u8, u32, Vec<(u32, Option<String>)>>;
+
+/// The articles that the link graph is restricted to, if any.
+static ALLOWLIST: OnceLock<Allowlist> = OnceLock::new();
+
+/// A set of articles that the link graph is restricted to.
+/// Only links between two articles in the set are kept, giving the subgraph induced by the set.
+struct Allowlist {
+    ids: HashSet<u32>,
+    /// A digest of the IDs in the set, which keeps the cached maps of different subgraphs apart.
+    digest: String,
+}
+
+/// Restricts the link graph to the articles listed in the given file, given as one ID or title per line.
+/// Link maps of the subgraph are cached separately from those of the whole graph.
+pub fn restrict_to_allowlist(path: &Path) -> anyhow::Result<()> {
+    let mut ids = HashSet::new();
+    let mut titles = Vec::new();
+    for line in BufReader::new(std::fs::File::open(path)?).lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        match line.parse::<u32>() {
+            Ok(id) => {
+                ids.insert(id);
+            }
+            Err(_) => titles.push(line.to_owned()),
+        }
+    }
+    if !titles.is_empty() {
+        let titles = titles.iter().map(String::as_str).collect::<Vec<_>>();
+        let title_ids = generate_title_map(false)?.get_ids(&titles)?;
+        for (title, id) in titles.into_iter().zip(title_ids) {
+            ids.insert(id.ok_or_else(|| anyhow::Error::msg(format!("article {title} not found")))?);
+        }
+    }
+
+    let mut context = md5::Context::new();
+    for id in ids.iter().sorted() {
+        context.consume(id.to_le_bytes());
+    }
+    let digest = format!("{:x}", context.compute())[..16].to_owned();
+
+    println!(
+        "Restricting the link graph to {} articles",
+        style(ids.len()).bold().bright()
+    );
+    ALLOWLIST
+        .set(Allowlist { ids, digest })
+        .map_err(|_| anyhow::Error::msg("the link graph was already restricted"))
+}
+
+/// Creates an empty link map with the given prefix.
+/// Link maps are stored in binary, since lists of IDs are several times larger as JSON.
+pub fn new_link_map(prefix: PathBuf) -> LinkMap {
+    let message = format!("Loading {}", prefix.display());
+    HierarchicalMap::new(prefix, id_short_key)
+        .with_binary_format()
+        .with_load_progress(message)
+}
+
+/// Whether links to redirects are replaced by links to the pages they redirect to.
+static RESOLVE_REDIRECTS: AtomicBool = AtomicBool::new(false);
+
+/// Makes every link to a redirect in the link graph point to the end of its chain of redirects instead.
+/// Link maps with resolved redirects are cached separately from those without.
+pub fn resolve_redirects_in_links() {
+    RESOLVE_REDIRECTS.store(true, Ordering::SeqCst);
+}
+
+/// Returns true if the given article is in the link graph, which is false only if it is not in the allowlist.
+pub fn is_in_link_graph(id: u32) -> bool {
+    ALLOWLIST
+        .get()
+        .is_none_or(|allowlist| allowlist.ids.contains(&id))
+}
+
+/// The prefix of the cached link map with the given name,
+/// which depends on whether redirects are resolved and on the allowlist that the link graph is restricted to.
+/// Other data derived from the link graph should be cached under such a prefix too.
+pub fn link_map_prefix(name: &str) -> PathBuf {
+    // The prefix must not contain a dot, since the map's files are named by replacing its extension.
+    let mut prefix = name.to_owned();
+    if RESOLVE_REDIRECTS.load(Ordering::SeqCst) {
+        prefix.push_str("_resolved");
+    }
+    if let Some(allowlist) = ALLOWLIST.get() {
+        prefix.push('_');
+        prefix.push_str(&allowlist.digest);
+    }
+    PathBuf::from(prefix)
+}
+
+/// Returns the redirect map if links to redirects should be resolved.
+/// Pass the result to [`resolve_link`] to find the article that stands for a page in the link graph.
+pub fn redirects_to_resolve(full: bool) -> anyhow::Result<Option<RedirectMap>> {
+    if RESOLVE_REDIRECTS.load(Ordering::SeqCst) {
+        Ok(Some(generate_redirects(full)?))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Replaces a link to a redirect by a link to the page at the end of its chain of redirects.
+/// If the chain is dangling or ends in a cycle, there is no such page, so the link to the redirect itself is kept.
+///
+/// When redirects are resolved, nothing in the link graph links to a redirect,
+/// so paths to or from a redirect must use the page it resolves to instead.
+pub fn resolve_link(redirects: Option<&RedirectMap>, id: u32) -> u32 {
+    match redirects.map(|redirects| resolve_redirect(redirects, id)) {
+        Some(RedirectResolution::Target(target)) => target,
+        _ => id,
+    }
+}
+
+pub fn execute(article: String, ignore_case: bool) -> anyhow::Result<()> {
     let title_map = generate_title_map(false)?;
     let outgoing_links = generate_outgoing_links(false)?;
     let incoming_links = generate_incoming_links(false)?;
 
-    let id = title_map.get_id(&canonicalise_wikilink(&article)).unwrap();
+    let id = title_map.resolve(&article, ignore_case)?;
     for link in outgoing_links.with(&id, |val| val.clone()).unwrap() {
         println!("> {}", title_map.get_title(link).unwrap());
     }
@@ -34,38 +159,37 @@ pub fn execute(article: String) -> anyhow::Result<()> {
 }
 
 pub fn generate_outgoing_links(full: bool) -> anyhow::Result<HierarchicalMap<u8, u32, Vec<u32>>> {
-    let map = HierarchicalMap::new(PathBuf::from("outgoing_links"), id_short_key);
+    let map = new_link_map(link_map_prefix("outgoing_links"));
     if map.deserialize(full)? {
         return Ok(map);
     }
 
     let title_map = generate_title_map(true)?;
+    let redirects = redirects_to_resolve(true)?;
 
     let red_links = Arc::new(AtomicUsize::new(0));
     let red_links2 = red_links.clone();
     let stream = page_stream(
-        u64::MAX,
+        StopCondition::ALL,
         1,
+        false,
         "Preprocessing outgoing links".to_string(),
         move |page| {
             (
                 page.id,
-                find_links(page.revision.text)
+                find_links(&page.revision.text)
                     .into_iter()
                     .map(|link| link.target_root())
-                    .filter(|root| {
-                        let (namespace, root_remainder) = split_namespace(root);
-                        let namespace_permitted =
-                            matches!(namespace, None | Some("Category") | Some("Portal"));
-                        namespace_permitted && !is_interwiki_link(root_remainder)
-                    })
+                    .filter(|root| is_graph_link(root))
                     .filter_map(|root| match title_map.get_id(&root) {
-                        Some(id) => Some(id),
+                        Some(id) => Some(resolve_link(redirects.as_ref(), id)),
                         None => {
                             red_links2.fetch_add(1, Ordering::SeqCst);
                             None
                         }
                     })
+                    // A link to a redirect back to the page itself resolves to a self-link.
+                    .filter(|id| redirects.is_none() || *id != page.id)
                     .unique()
                     .collect::<Vec<_>>(),
             )
@@ -73,7 +197,13 @@ pub fn generate_outgoing_links(full: bool) -> anyhow::Result<HierarchicalMap<u8,
     )?;
 
     let mut blue_links = 0;
-    for (page, links) in stream {
+    for (page, mut links) in stream {
+        if let Some(allowlist) = ALLOWLIST.get() {
+            if !allowlist.ids.contains(&page) {
+                continue;
+            }
+            links.retain(|link| allowlist.ids.contains(link));
+        }
         blue_links += links.len();
         map.insert(page, links);
     }
@@ -89,20 +219,50 @@ pub fn generate_outgoing_links(full: bool) -> anyhow::Result<HierarchicalMap<u8,
     Ok(map)
 }
 
-pub fn generate_incoming_links(full: bool) -> anyhow::Result<HierarchicalMap<u8, u32, Vec<u32>>> {
-    let map = HierarchicalMap::new(PathBuf::from("incoming_links"), id_short_key);
+/// Computes the outgoing links from each article, together with the section of the article that each link appears in,
+/// or [`None`] for links in the lead.
+/// A link that appears in several sections is listed once for each section.
+/// This is much larger than the plain outgoing link map, so it is cached separately and only computed on demand.
+pub fn generate_outgoing_links_by_section(full: bool) -> anyhow::Result<SectionLinkMap> {
+    let map = HierarchicalMap::new(link_map_prefix("outgoing_links_by_section"), id_short_key);
     if map.deserialize(full)? {
         return Ok(map);
     }
 
-    let outgoing_links = generate_outgoing_links(true)?;
-    let rx = outgoing_links.with_all("Preprocessing incoming links".to_owned(), |id, links| {
-        (*id, links.to_owned())
-    });
-    while let Ok((id, links)) = rx.recv() {
-        for link in links {
-            map.mutate_with_default(link, |list| list.push(id));
+    let title_map = generate_title_map(true)?;
+    let redirects = redirects_to_resolve(true)?;
+
+    let stream = page_stream(
+        StopCondition::ALL,
+        1,
+        false,
+        "Preprocessing outgoing links by section".to_string(),
+        move |page| {
+            (
+                page.id,
+                find_links_by_section(&page.revision.text)
+                    .into_iter()
+                    .map(|(link, section)| (link.target_root(), section))
+                    .filter(|(root, _)| is_graph_link(root))
+                    .filter_map(|(root, section)| {
+                        let id = resolve_link(redirects.as_ref(), title_map.get_id(&root)?);
+                        Some((id, section))
+                    })
+                    .filter(|(id, _)| redirects.is_none() || *id != page.id)
+                    .unique()
+                    .collect::<Vec<_>>(),
+            )
+        },
+    )?;
+
+    for (page, mut links) in stream {
+        if let Some(allowlist) = ALLOWLIST.get() {
+            if !allowlist.ids.contains(&page) {
+                continue;
+            }
+            links.retain(|(link, _)| allowlist.ids.contains(link));
         }
+        map.insert(page, links);
     }
 
     map.mark_loaded();
@@ -110,3 +270,181 @@ pub fn generate_incoming_links(full: bool) -> anyhow::Result<HierarchicalMap<u8,
 
     Ok(map)
 }
+
+/// Computes the incoming links to each article by transposing the outgoing link map.
+///
+/// To keep memory usage down, this is done one bucket at a time.
+/// We first read each bucket of the outgoing link map from disk, scattering each reversed link
+/// into a temporary file for the bucket of its target.
+/// Then we load each of these temporary files in turn, and serialise it as a bucket of the incoming link map.
+/// This way, we only hold one bucket of either map in memory at once.
+pub fn generate_incoming_links(full: bool) -> anyhow::Result<LinkMap> {
+    let map = new_link_map(link_map_prefix("incoming_links"));
+    if map.deserialize(full)? {
+        return Ok(map);
+    }
+
+    let outgoing_links = generate_outgoing_links(false)?;
+    let temp_dir = data_dir().join(format!(
+        "{}.tmp",
+        link_map_prefix("incoming_links").display()
+    ));
+    if temp_dir.exists() {
+        // Clear out anything left over from an interrupted run.
+        std::fs::remove_dir_all(&temp_dir)?;
+    }
+    std::fs::create_dir_all(&temp_dir)?;
+    let temp_path = |short_key: u8| temp_dir.join(short_key.to_string()).with_extension("tsv");
+
+    {
+        let short_keys = outgoing_links.short_keys();
+        let progress_bar =
+            normal_progress_bar(short_keys.len() as u64).with_message("Scattering incoming links");
+        let mut writers = HashMap::new();
+        for short_key in short_keys {
+            outgoing_links.for_each_in_bucket_file(&short_key, |id: u32, links: Vec<u32>| {
+                for link in links {
+                    let target_short_key = id_short_key(&link);
+                    let writer = match writers.entry(target_short_key) {
+                        Entry::Occupied(entry) => entry.into_mut(),
+                        Entry::Vacant(entry) => entry.insert(BufWriter::new(
+                            std::fs::File::create(temp_path(target_short_key))?,
+                        )),
+                    };
+                    writeln!(writer, "{link}\t{id}")?;
+                }
+                Ok(())
+            })?;
+            progress_bar.inc(1);
+        }
+        for writer in writers.values_mut() {
+            writer.flush()?;
+        }
+        progress_bar.finish();
+    }
+
+    let mut target_short_keys = std::fs::read_dir(&temp_dir)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    target_short_keys.sort();
+    let progress_bar = normal_progress_bar(target_short_keys.len() as u64)
+        .with_message("Gathering incoming links");
+    for path in target_short_keys {
+        // Each line of the temporary file is a reversed link, and links appear in the same order
+        // as they would if we had transposed the whole map in memory.
+        for line in BufReader::new(std::fs::File::open(&path)?).lines() {
+            let line = line?;
+            let (link, id) = line
+                .split_once('\t')
+                .ok_or_else(|| anyhow::Error::msg(format!("malformed line {line}")))?;
+            let id: u32 = id.parse()?;
+            map.mutate_with_default(link.parse()?, |list: &mut Vec<u32>| list.push(id));
+        }
+        let short_key = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse::<u8>().ok())
+            .ok_or_else(|| anyhow::Error::msg(format!("unexpected file {}", path.display())))?;
+        map.serialize_bucket(&short_key)?;
+        std::fs::remove_file(&path)?;
+        progress_bar.inc(1);
+    }
+    progress_bar.finish();
+
+    map.serialize_short_keys()?;
+    std::fs::remove_dir(&temp_dir)?;
+
+    // Now that every bucket is on disk, load the map in the way that was asked for.
+    let map = new_link_map(link_map_prefix("incoming_links"));
+    map.deserialize(full)?;
+    Ok(map)
+}
+
+/// Returns true if a link to this canonical target root belongs in the link graph.
+/// Links to the current page, such as `[[#Section]]`, have an empty root and are ignored,
+/// as are links outside the main, category and portal namespaces, and interwiki links.
+fn is_graph_link(root: &str) -> bool {
+    if root.is_empty() {
+        return false;
+    }
+    // Titles such as `C: drive` have no namespace, so they are kept.
+    let (namespace, root_remainder) = split_namespace(root);
+    let namespace_permitted = matches!(namespace, None | Some("Category") | Some("Portal"));
+    namespace_permitted && !is_interwiki_link(root_remainder)
+}
+
+/// Computes the number of outgoing links from each article.
+pub fn generate_out_degree(full: bool) -> anyhow::Result<DegreeMap> {
+    generate_degree("out_degree", "outgoing", full, generate_outgoing_links)
+}
+
+/// Computes the number of incoming links to each article.
+pub fn generate_in_degree(full: bool) -> anyhow::Result<DegreeMap> {
+    generate_degree("in_degree", "incoming", full, generate_incoming_links)
+}
+
+fn generate_degree(
+    prefix: &str,
+    direction: &str,
+    full: bool,
+    generate_links: fn(bool) -> anyhow::Result<LinkMap>,
+) -> anyhow::Result<DegreeMap> {
+    let map = HierarchicalMap::new(link_map_prefix(prefix), id_short_key);
+    if map.deserialize(full)? {
+        return Ok(map);
+    }
+
+    let links = generate_links(true)?;
+    let rx = links.par_with_all(format!("Counting {direction} links"), |id, links| {
+        (*id, links.len() as u32)
+    });
+    while let Ok((id, degree)) = rx.recv() {
+        map.insert(id, degree);
+    }
+
+    map.mark_loaded();
+    map.serialize()?;
+
+    Ok(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::titles::canonicalise_wikilink;
+
+    /// The canonical roots of the links in this wikitext that belong in the link graph.
+    fn graph_links(text: &str) -> Vec<String> {
+        find_links(text)
+            .into_iter()
+            .map(|link| link.target_root())
+            .filter(|root| is_graph_link(root))
+            .collect()
+    }
+
+    #[test]
+    fn empty_targets_are_ignored() {
+        assert_eq!(graph_links("[[|x]]"), Vec::<String>::new());
+        assert_eq!(graph_links("see [[#History]]"), Vec::<String>::new());
+        assert_eq!(graph_links("[[]]"), Vec::<String>::new());
+        assert_eq!(canonicalise_wikilink(""), "");
+        assert!(!is_graph_link(""));
+    }
+
+    #[test]
+    fn titles_with_colons_outside_namespaces_are_kept() {
+        assert_eq!(
+            graph_links("[[Nineteen Eighty-Four: The Musical]] [[c: drive]]"),
+            vec!["Nineteen Eighty-Four: The Musical", "C: drive"]
+        );
+        assert_eq!(
+            graph_links("[[Category:Musicals]] [[Template:Infobox]] [[wikibooks:Foo]]"),
+            vec!["Category:Musicals"]
+        );
+    }
+
+    #[test]
+    fn empty_targets_do_not_hide_other_links() {
+        assert_eq!(graph_links("[[|x]] [[#sec]] [[foo]]"), vec!["Foo"]);
+    }
+}
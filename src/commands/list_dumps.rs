@@ -0,0 +1,48 @@
+use console::style;
+use indicatif::HumanBytes;
+
+use crate::progress_bar::message_spinner;
+
+use super::download::{Client, DEFAULT_RETRIES};
+
+/// Lists the available versions of the dump, their total size, and whether each has finished,
+/// without downloading any data files.
+pub fn execute() -> anyhow::Result<()> {
+    let client = Client::new(DEFAULT_RETRIES);
+
+    let spinner = message_spinner().with_message("Downloading dumps list");
+    let versions = client.dump_versions()?;
+    let mut statuses = Vec::new();
+    for version in versions {
+        spinner.set_message(format!(
+            "Downloading dump information for version {}",
+            style(&version).bright().bold()
+        ));
+        let status = client.dump_status(&version);
+        statuses.push((version, status));
+    }
+    spinner.finish_and_clear();
+
+    for (version, status) in statuses {
+        let size = match &status {
+            Ok(status) => HumanBytes(
+                status
+                    .jobs
+                    .all_files()
+                    .iter()
+                    .map(|(_, file)| file.size)
+                    .sum(),
+            )
+            .to_string(),
+            Err(_) => String::new(),
+        };
+        let status = match status {
+            Ok(status) if status.jobs.done() => style("done".to_owned()).green(),
+            Ok(_) => style("waiting".to_owned()).yellow(),
+            Err(err) => style(format!("unavailable ({err})")).red(),
+        };
+        println!("{}  {size:>10}  {status}", style(version).bold());
+    }
+
+    Ok(())
+}
@@ -1,20 +1,29 @@
-use std::io::Write;
+use std::fmt::Write;
 use std::sync::{
     atomic::{AtomicUsize, Ordering},
-    Arc,
+    Arc, Mutex,
 };
 
 use console::style;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    commands::{random_article::random_article_id, shortest_path},
-    page::get_dump_status,
+    atomic_write::write_atomic,
+    commands::{
+        random_article::random_article_id,
+        shortest_path::{SolverCache, ENDS_PER_START},
+    },
+    data_dir::data_dir,
+    page::{get_dump_status, PageReader},
+    progress_bar::{status_spinner, ProgressHandle},
     titles::generate_title_map,
 };
 
 use super::links::{generate_incoming_links, generate_outgoing_links};
 
-pub fn execute() -> anyhow::Result<()> {
+/// Repeatedly finds the shortest path between random pairs of articles, reporting each new longest path.
+/// Paths of degree less than `min_length` are tracked but not printed.
+pub fn execute(min_length: usize) -> anyhow::Result<()> {
     let dump_status = get_dump_status()?;
     println!("Loading title map");
     let title_map = generate_title_map(true)?;
@@ -24,37 +33,76 @@ pub fn execute() -> anyhow::Result<()> {
     let incoming_links = generate_incoming_links(true)?;
     println!("All data loaded.");
 
-    let longest_path_length = Arc::new(AtomicUsize::new(0));
-    let paths_tried = Arc::new(AtomicUsize::new(0));
-    let tasks = (0..16)
+    let state = LongPathsState::load()?;
+    let longest_path_length = Arc::new(AtomicUsize::new(state.longest_path_length));
+    let status = status_spinner().with_message("No paths found yet; paths tried");
+    status.set_position(state.paths_tried);
+    if state.longest_path_length > 0 {
+        status.set_message(format!(
+            "Best degree {}; paths tried",
+            state.longest_path_length - 1
+        ));
+    }
+
+    {
+        // Save the search state when interrupted, so that the next run can carry on from here.
+        let longest_path_length = longest_path_length.clone();
+        let status = status.clone();
+        ctrlc::set_handler(move || {
+            if let Err(err) = LongPathsState::current(&status, &longest_path_length).save() {
+                eprintln!("could not save search state: {err}");
+            }
+            std::process::exit(130);
+        })?;
+    }
+
+    const THREADS: usize = 16;
+    // Each thread searches from one start article at a time.
+    let cache = Arc::new(SolverCache::new(THREADS));
+    let tasks = (0..THREADS)
         .map(|_| {
             let dump_status = dump_status.clone();
             let title_map = title_map.clone();
             let outgoing_links = outgoing_links.clone();
             let incoming_links = incoming_links.clone();
+            let cache = cache.clone();
 
             let longest_path_length = longest_path_length.clone();
-            let paths_tried = paths_tried.clone();
+            let status = status.clone();
             std::thread::spawn::<_, anyhow::Result<()>>(move || {
+                let mut page_reader = PageReader::new(&dump_status)?;
+                // A very simple algorithm to find some long paths: randomly select pairs of articles
+                // and compute the shortest distance between them.
+                // Several end articles are tried for each start article, so that the searches can share its levels.
+                let mut start = random_article_id(&mut page_reader, &title_map, true)?;
+                let mut ends_tried = 0;
                 loop {
-                    // A very simple algorithm to find some long paths: randomly select a pair of articles
-                    // and compute the shortest distance between them.
-                    let start = random_article_id(&dump_status, &title_map, true)?;
-                    let end = random_article_id(&dump_status, &title_map, true)?;
-                    let path = shortest_path::Solver::new(start, end).solve(
-                        &outgoing_links,
-                        &incoming_links,
-                        false,
-                    );
-                    let paths_tried = paths_tried.fetch_add(1, Ordering::SeqCst);
-                    if paths_tried % 100 == 0 {
-                        println!("Tried {paths_tried} paths");
+                    if ends_tried == ENDS_PER_START {
+                        start = random_article_id(&mut page_reader, &title_map, true)?;
+                        ends_tried = 0;
+                    }
+                    ends_tried += 1;
+                    let end = random_article_id(&mut page_reader, &title_map, true)?;
+                    let mut solver = cache.solver(start, end);
+                    let path = solver.solve(&outgoing_links, &incoming_links, None, false);
+                    cache.record(&solver);
+                    status.inc(1);
+                    if status.position().is_multiple_of(SAVE_INTERVAL) {
+                        LongPathsState::current(&status, &longest_path_length).save()?;
                     }
                     if let Some(path) = path {
                         if path.len() >= longest_path_length.load(Ordering::SeqCst) {
                             longest_path_length.fetch_max(path.len(), Ordering::SeqCst);
+                            status.set_message(format!(
+                                "Best degree {}; paths tried",
+                                longest_path_length.load(Ordering::SeqCst) - 1
+                            ));
 
-                            let mut out = std::io::stdout().lock();
+                            if path.len() - 1 < min_length {
+                                continue;
+                            }
+
+                            let mut out = String::new();
                             writeln!(
                                 out,
                                 "\nMinimal path of degree {} found!",
@@ -70,7 +118,8 @@ pub fn execute() -> anyhow::Result<()> {
                                     writeln!(out, "{:>5} {}", style(format!("{i}.")).dim(), title)?;
                                 }
                             }
-                            writeln!(out)?;
+                            // Print the path above the status line rather than overwriting it.
+                            status.suspend(|| println!("{out}"));
                         }
                     }
                 }
@@ -84,3 +133,45 @@ pub fn execute() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// How many paths we try between saves of the search state.
+const SAVE_INTERVAL: u64 = 100;
+
+/// The file in the data directory where the search state is saved.
+const STATE_FILE: &str = "long_paths_state.json";
+
+/// Ensures that only one thread writes the search state at a time.
+static SAVE_LOCK: Mutex<()> = Mutex::new(());
+
+/// The state of the search that is persisted between runs,
+/// so that repeated invocations accumulate progress rather than starting afresh.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct LongPathsState {
+    paths_tried: u64,
+    /// The number of articles in the longest minimal path found so far.
+    longest_path_length: usize,
+}
+
+impl LongPathsState {
+    fn current(status: &ProgressHandle, longest_path_length: &AtomicUsize) -> Self {
+        Self {
+            paths_tried: status.position(),
+            longest_path_length: longest_path_length.load(Ordering::SeqCst),
+        }
+    }
+
+    fn load() -> anyhow::Result<Self> {
+        match std::fs::read_to_string(data_dir().join(STATE_FILE)) {
+            Ok(text) => Ok(serde_json::from_str(&text)?),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        let _guard = SAVE_LOCK.lock().unwrap();
+        write_atomic(&data_dir().join(STATE_FILE), |writer| {
+            Ok(serde_json::to_writer_pretty(writer, self)?)
+        })
+    }
+}
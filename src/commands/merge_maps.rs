@@ -0,0 +1,42 @@
+use std::path::PathBuf;
+
+use console::style;
+
+use super::links::{new_link_map, LinkMap};
+
+/// Merges two serialised link maps, such as `outgoing_links` from two different dumps,
+/// into a single map stored under `output`.
+pub fn execute(first: PathBuf, second: PathBuf, output: PathBuf) -> anyhow::Result<()> {
+    let merged = new_link_map(output.clone());
+    for prefix in [first, second] {
+        let map = load_link_map(prefix)?;
+        merged.merge(&map, |links: &mut Vec<u32>, other_links| {
+            for link in other_links {
+                if !links.contains(link) {
+                    links.push(*link);
+                }
+            }
+        })?;
+    }
+
+    merged.mark_loaded();
+    println!("{merged}");
+    merged.serialize()?;
+    println!(
+        "Merged map written to {}",
+        style(output.display()).bold().bright()
+    );
+
+    Ok(())
+}
+
+fn load_link_map(prefix: PathBuf) -> anyhow::Result<LinkMap> {
+    let map = new_link_map(prefix.clone());
+    if !map.deserialize(true)? {
+        return Err(anyhow::Error::msg(format!(
+            "no map was found with prefix {}",
+            prefix.display()
+        )));
+    }
+    Ok(map)
+}
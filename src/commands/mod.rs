@@ -3,3 +3,24 @@ pub mod random_article;
 pub mod links;
 pub mod shortest_path;
 pub mod long_paths;
+pub mod merge_maps;
+pub mod redirects;
+pub mod export;
+pub mod reciprocity;
+pub mod bucket;
+pub mod batch_path;
+pub mod word_count;
+pub mod tree;
+pub mod density;
+pub mod one_way;
+pub mod prune;
+pub mod random_path;
+pub mod block;
+pub mod bench;
+pub mod list_dumps;
+pub mod hubs;
+pub mod verify;
+pub mod distance;
+pub mod see_also;
+pub mod check_titles;
+pub mod stats;
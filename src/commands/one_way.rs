@@ -0,0 +1,60 @@
+use std::collections::HashSet;
+
+use console::style;
+
+use crate::titles::generate_title_map;
+
+use super::links::{generate_incoming_links, generate_outgoing_links};
+
+/// Lists the articles that link to the given article without being linked back.
+/// If `reverse` is true, also lists the articles that the given article links to that don't link back.
+pub fn execute(article: String, reverse: bool) -> anyhow::Result<()> {
+    let title_map = generate_title_map(false)?;
+    let outgoing_links = generate_outgoing_links(false)?;
+    let incoming_links = generate_incoming_links(false)?;
+
+    let id = title_map.resolve(&article, false)?;
+    let outgoing = outgoing_links
+        .with(&id, |links| links.iter().copied().collect::<HashSet<_>>())
+        .unwrap_or_default();
+    let incoming = incoming_links
+        .with(&id, |links| links.iter().copied().collect::<HashSet<_>>())
+        .unwrap_or_default();
+
+    let title = title_map.get_title(id).unwrap();
+    print_difference(
+        &format!("Articles linking to {title} that it doesn't link back to"),
+        &incoming,
+        &outgoing,
+        |id| title_map.get_title(id).unwrap(),
+    );
+    if reverse {
+        println!();
+        print_difference(
+            &format!("Articles linked from {title} that don't link back to it"),
+            &outgoing,
+            &incoming,
+            |id| title_map.get_title(id).unwrap(),
+        );
+    }
+
+    Ok(())
+}
+
+/// Prints the titles of the articles in `left` but not `right`, in alphabetical order.
+fn print_difference(
+    heading: &str,
+    left: &HashSet<u32>,
+    right: &HashSet<u32>,
+    title: impl Fn(u32) -> String,
+) {
+    let mut titles = left
+        .difference(right)
+        .map(|id| title(*id))
+        .collect::<Vec<_>>();
+    titles.sort();
+    println!("{} ({}):", heading, style(titles.len()).bold().bright());
+    for title in titles {
+        println!("  {title}");
+    }
+}
@@ -0,0 +1,114 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use console::style;
+use indicatif::HumanBytes;
+
+use crate::data_dir::data_dir;
+
+/// A bucket file of one of the cached hierarchical maps.
+struct BucketFile {
+    /// The map's prefix, such as `outgoing_links`.
+    map: PathBuf,
+    short_key: String,
+    /// The extension of the bucket file, which depends on whether the map is stored as JSON lines or in binary.
+    extension: String,
+    size: u64,
+    accessed: SystemTime,
+}
+
+/// Deletes the least recently accessed bucket files of the cached hierarchical maps
+/// until they take up at most `max_size` bytes.
+///
+/// Buckets can't be rebuilt on their own, so a map with any pruned buckets is regenerated in full,
+/// rewriting every bucket, the next time it is needed.
+pub fn execute(max_size: u64) -> anyhow::Result<()> {
+    let data = data_dir();
+    let mut buckets = Vec::new();
+    find_bucket_files(data, data, &mut buckets)?;
+
+    let total_size = buckets.iter().map(|bucket| bucket.size).sum::<u64>();
+    println!(
+        "Found {} cache buckets using {}",
+        style(buckets.len()).bold().bright(),
+        style(HumanBytes(total_size)).bold().bright()
+    );
+    if total_size <= max_size {
+        println!("Nothing to prune.");
+        return Ok(());
+    }
+
+    buckets.sort_by_key(|bucket| bucket.accessed);
+    let mut size = total_size;
+    let mut pruned = BTreeMap::<PathBuf, usize>::new();
+    for bucket in buckets {
+        if size <= max_size {
+            break;
+        }
+        let map_path = data.join(&bucket.map);
+        std::fs::remove_file(
+            map_path
+                .join(&bucket.short_key)
+                .with_extension(&bucket.extension),
+        )?;
+        size -= bucket.size;
+        *pruned.entry(bucket.map).or_default() += 1;
+    }
+
+    for (map, count) in &pruned {
+        println!(
+            "Pruned {} buckets of {}, which will be regenerated in full when next needed",
+            style(count).bold().bright(),
+            map.display()
+        );
+    }
+    println!(
+        "Cache buckets now use {}",
+        style(HumanBytes(size)).bold().bright()
+    );
+
+    Ok(())
+}
+
+/// Finds the bucket files of every hierarchical map under `dir`.
+/// A hierarchical map with prefix `map` is stored as a file `map.json` listing its short keys,
+/// and a directory `map/` containing a file `<short key>.jsonl` or `<short key>.bin` for each bucket.
+fn find_bucket_files(root: &Path, dir: &Path, buckets: &mut Vec<BucketFile>) -> anyhow::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !path.is_dir() {
+            continue;
+        }
+        if path.with_extension("json").is_file() {
+            for bucket in std::fs::read_dir(&path)? {
+                let bucket = bucket?;
+                let bucket_path = bucket.path();
+                let Some(extension) = bucket_path
+                    .extension()
+                    .filter(|extension| *extension == "jsonl" || *extension == "bin")
+                else {
+                    continue;
+                };
+                let metadata = bucket.metadata()?;
+                buckets.push(BucketFile {
+                    map: path.strip_prefix(root)?.to_owned(),
+                    short_key: bucket_path
+                        .file_stem()
+                        .unwrap()
+                        .to_string_lossy()
+                        .into_owned(),
+                    extension: extension.to_string_lossy().into_owned(),
+                    size: metadata.len(),
+                    // Access times aren't always recorded, so fall back to the modification time.
+                    accessed: metadata.accessed().or_else(|_| metadata.modified())?,
+                });
+            }
+        } else {
+            find_bucket_files(root, &path, buckets)?;
+        }
+    }
+    Ok(())
+}
@@ -1,17 +1,15 @@
 use rand::Rng;
 
 use crate::{
-    page::{get_dump_status, page_information},
+    page::{get_dump_status, PageError, PageReader},
     titles::{generate_title_map, split_namespace, TitleMap},
 };
 
-use super::download::DumpStatus;
-
 pub fn execute() -> anyhow::Result<()> {
     let dump_status = get_dump_status()?;
     let title_map = generate_title_map(false)?;
 
-    let id = random_article_id(&dump_status, &title_map, true)?;
+    let id = random_article_id(&mut PageReader::new(&dump_status)?, &title_map, true)?;
     println!("Chosen random article {}", title_map.get_title(id).unwrap());
 
     Ok(())
@@ -19,7 +17,7 @@ pub fn execute() -> anyhow::Result<()> {
 
 /// If `root_namespace` is true, we return only articles in the root namespace.
 pub fn random_article_id(
-    dump_status: &DumpStatus,
+    page_reader: &mut PageReader,
     title_map: &TitleMap,
     root_namespace: bool,
 ) -> anyhow::Result<u32> {
@@ -27,7 +25,18 @@ pub fn random_article_id(
         let random_id = rand::thread_rng().gen_range(0..100_000_000u32);
         if let Some(title) = title_map.get_title(random_id) {
             let is_redirect =
-                page_information(dump_status, random_id, |page| page.redirect.is_some())?;
+                match page_reader.information(random_id, |page| page.redirect.is_some()) {
+                    Ok(is_redirect) => is_redirect,
+                    // The title map may be out of date with respect to the articles files, so just try another ID.
+                    Err(err)
+                        if err
+                            .downcast_ref::<PageError>()
+                            .is_some_and(PageError::is_missing) =>
+                    {
+                        continue
+                    }
+                    Err(err) => return Err(err),
+                };
             let (namespace, _) = split_namespace(&title);
             if !is_redirect && (!root_namespace || namespace.is_none()) {
                 break Ok(random_id);
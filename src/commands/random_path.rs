@@ -0,0 +1,71 @@
+use console::style;
+
+use crate::{
+    commands::random_article::random_article_id,
+    page::{get_dump_status, PageReader},
+    progress_bar::status_spinner,
+    titles::generate_title_map,
+};
+
+use super::{
+    links::{generate_incoming_links, generate_outgoing_links},
+    shortest_path::{SolverCache, ENDS_PER_START},
+};
+
+/// Solves paths between random pairs of articles until finding a minimal path of the given degree.
+/// Fails if no such path is found after trying `max_attempts` pairs, since there may be none at all.
+pub fn execute(degree: usize, max_attempts: u64) -> anyhow::Result<()> {
+    let dump_status = get_dump_status()?;
+    println!("Loading title map");
+    let title_map = generate_title_map(true)?;
+    println!("Loading outgoing link map");
+    let outgoing_links = generate_outgoing_links(true)?;
+    println!("Loading incoming link map");
+    let incoming_links = generate_incoming_links(true)?;
+    println!("All data loaded.");
+
+    let mut page_reader = PageReader::new(&dump_status)?;
+    let cache = SolverCache::new(1);
+    let status =
+        status_spinner().with_message(format!("Searching for degree {degree}; paths tried"));
+    let path = 'search: loop {
+        let start = random_article_id(&mut page_reader, &title_map, true)?;
+        for _ in 0..ENDS_PER_START {
+            if status.position() >= max_attempts {
+                status.finish_and_clear();
+                return Err(anyhow::Error::msg(format!(
+                    "no minimal path of degree {degree} found after trying {max_attempts} paths"
+                )));
+            }
+            let end = random_article_id(&mut page_reader, &title_map, true)?;
+            let mut solver = cache.solver(start, end);
+            let path = solver.solve(&outgoing_links, &incoming_links, None, false);
+            cache.record(&solver);
+            status.inc(1);
+            if let Some(path) = path {
+                if path.len() - 1 == degree {
+                    break 'search path;
+                }
+            }
+        }
+    };
+    status.finish_and_clear();
+
+    println!(
+        "Minimal path of degree {} found after trying {} paths!",
+        style(path.len() - 1).bold().bright(),
+        status.position()
+    );
+    for (i, item) in path.iter().enumerate() {
+        let title = title_map.get_title(*item).unwrap();
+        if i == 0 {
+            println!("{} {}", style("start").red(), title);
+        } else if i == path.len() - 1 {
+            println!("  {} {}", style("end").green(), title);
+        } else {
+            println!("{:>5} {}", style(format!("{i}.")).dim(), title)
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use console::style;
+use rand::{seq::SliceRandom, Rng};
+
+use crate::{
+    hierarchical_map::HierarchicalMap, progress_bar::normal_progress_bar,
+    titles::generate_title_map,
+};
+
+use super::links::generate_outgoing_links;
+
+/// Computes the fraction of links `a → b` for which `b → a` is also a link.
+/// If `exact` is false, we estimate this by sampling `samples` links.
+/// If `exact` is true, we check every link, and can report the `top` articles with the most reciprocal links.
+pub fn execute(samples: u64, exact: bool, top: Option<usize>) -> anyhow::Result<()> {
+    let outgoing_links = generate_outgoing_links(exact)?;
+
+    let (reciprocal, total) = if exact {
+        let title_map = generate_title_map(false)?;
+        let (reciprocal, total, mut per_article) = exact_reciprocity(&outgoing_links);
+        if let Some(top) = top {
+            per_article.sort_unstable_by_key(|(id, count)| (std::cmp::Reverse(*count), *id));
+            println!("Articles with the most reciprocal links:");
+            for (id, count) in per_article.into_iter().take(top) {
+                println!(
+                    "{:>8} {}",
+                    style(count).bold().bright(),
+                    title_map.get_title(id).unwrap()
+                );
+            }
+        }
+        (reciprocal, total)
+    } else {
+        sampled_reciprocity(&outgoing_links, samples)
+    };
+
+    println!(
+        "\n{} of {} links are reciprocal (reciprocity {})",
+        style(reciprocal).bold().bright(),
+        style(total).bold().bright(),
+        style(format!("{:.4}", reciprocal as f64 / total.max(1) as f64))
+            .bold()
+            .bright()
+    );
+
+    Ok(())
+}
+
+fn has_link(outgoing_links: &HierarchicalMap<u8, u32, Vec<u32>>, from: u32, to: u32) -> bool {
+    outgoing_links
+        .with(&from, |links| links.contains(&to))
+        .unwrap_or(false)
+}
+
+/// Samples links by choosing a random article with outgoing links, then a random one of its links.
+/// Note that this weights each article equally, rather than each link.
+fn sampled_reciprocity(
+    outgoing_links: &HierarchicalMap<u8, u32, Vec<u32>>,
+    samples: u64,
+) -> (u64, u64) {
+    let progress_bar = normal_progress_bar(samples).with_message("Sampling links");
+    let mut rng = rand::thread_rng();
+    let mut reciprocal = 0;
+    while progress_bar.position() < samples {
+        let random_id = rng.gen_range(0..100_000_000u32);
+        let Some(Some(link)) =
+            outgoing_links.with(&random_id, |links| links.choose(&mut rng).copied())
+        else {
+            continue;
+        };
+        if has_link(outgoing_links, link, random_id) {
+            reciprocal += 1;
+        }
+        progress_bar.inc(1);
+    }
+    progress_bar.finish();
+    (reciprocal, samples)
+}
+
+/// Returns the number of reciprocal links, the total number of links,
+/// and the number of reciprocal links from each article.
+fn exact_reciprocity(
+    outgoing_links: &HierarchicalMap<u8, u32, Vec<u32>>,
+) -> (u64, u64, Vec<(u32, u64)>) {
+    let rx = {
+        let outgoing_links_handle = outgoing_links.clone();
+        outgoing_links.par_with_all("Checking links".to_owned(), move |id, links| {
+            let count = links
+                .iter()
+                .filter(|link| has_link(&outgoing_links_handle, **link, *id))
+                .count() as u64;
+            (*id, count, links.len() as u64)
+        })
+    };
+    let mut reciprocal = 0;
+    let mut total = 0;
+    let mut per_article = Vec::new();
+    while let Ok((id, count, links)) = rx.recv() {
+        reciprocal += count;
+        total += links;
+        if count > 0 {
+            per_article.push((id, count));
+        }
+    }
+    (reciprocal, total, per_article)
+}
@@ -0,0 +1,206 @@
+use std::{
+    collections::BTreeSet,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+use console::style;
+use flate2::bufread::GzDecoder;
+
+use crate::{
+    commands::download::JobStatus,
+    data_dir::data_dir,
+    hierarchical_map::HierarchicalMap,
+    page::{get_dump_status, page_stream, StopCondition},
+    parse::{sql::parse_insert_values, wikitext::parse_redirect},
+    progress_bar::file_progress_bar,
+    titles::{generate_title_map, id_short_key, TitleMap},
+};
+
+/// A map from the ID of each redirect page to the ID of the page it redirects to.
+/// If the target of the redirect does not exist, the value is [`None`].
+pub type RedirectMap = HierarchicalMap<u8, u32, Option<u32>>;
+
+pub fn execute(detect_cycles: bool) -> anyhow::Result<()> {
+    let title_map = generate_title_map(false)?;
+    let redirects = generate_redirects(detect_cycles)?;
+
+    if !detect_cycles {
+        println!("{redirects}");
+        return Ok(());
+    }
+
+    let rx = redirects.with_all("Following redirects".to_owned(), |id, target| {
+        (*id, target.is_none())
+    });
+    let mut dangling = Vec::new();
+    let mut cycles = BTreeSet::new();
+    while let Ok((id, is_dangling)) = rx.recv() {
+        if is_dangling {
+            dangling.push(id);
+        } else if let RedirectResolution::Cycle(mut cycle) = resolve_redirect(&redirects, id) {
+            // Rotate the cycle so that it starts at its smallest ID, so each cycle is only reported once.
+            let min_index = (0..cycle.len()).min_by_key(|i| cycle[*i]).unwrap();
+            cycle.rotate_left(min_index);
+            cycles.insert(cycle);
+        }
+    }
+
+    println!(
+        "\nFound {} redirect cycles",
+        style(cycles.len()).bold().bright()
+    );
+    for cycle in &cycles {
+        let titles = cycle
+            .iter()
+            .chain(cycle.first())
+            .map(|id| title_or_id(&title_map, *id))
+            .collect::<Vec<_>>();
+        println!("  {}", titles.join(" → "));
+    }
+
+    println!(
+        "\nFound {} dangling redirects",
+        style(dangling.len()).bold().bright()
+    );
+    for id in dangling {
+        println!("  {}", title_or_id(&title_map, id));
+    }
+
+    Ok(())
+}
+
+fn title_or_id(title_map: &TitleMap, id: u32) -> String {
+    title_map
+        .get_title(id)
+        .unwrap_or_else(|| format!("<id {id}>"))
+}
+
+pub fn generate_redirects(full: bool) -> anyhow::Result<RedirectMap> {
+    let map = HierarchicalMap::new(PathBuf::from("redirects"), id_short_key);
+    if map.deserialize(full)? {
+        return Ok(map);
+    }
+
+    let title_map = generate_title_map(true)?;
+
+    // If we downloaded the SQL dump of the redirect table, we can use that instead of streaming every page.
+    let dump_status = get_dump_status()?;
+    let redirect_table = dump_status
+        .jobs
+        .redirect_table
+        .as_ref()
+        .map(JobStatus::files)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(_, status)| data_dir().join(status.url))
+        .find(|path| path.is_file());
+    if let Some(path) = redirect_table {
+        read_redirect_table(&map, &title_map, &path)?;
+        map.mark_loaded();
+        map.serialize()?;
+        return Ok(map);
+    }
+
+    let stream = page_stream(
+        StopCondition::ALL,
+        1,
+        false,
+        "Preprocessing redirects".to_string(),
+        move |page| {
+            // Some dumps omit the redirect element, so fall back to the `#REDIRECT` line of the text.
+            let target = match page.redirect {
+                Some(target) => Some(title_map.get_id(&target)),
+                None => parse_redirect(&page.revision.text)
+                    .map(|link| title_map.get_id(&link.target_root())),
+            };
+            target.map(|target| (page.id, target))
+        },
+    )?;
+
+    for (page, target) in stream.into_iter().flatten() {
+        map.insert(page, target);
+    }
+
+    map.mark_loaded();
+    map.serialize()?;
+
+    Ok(map)
+}
+
+/// Populates the redirect map using the SQL dump of the redirect table, found at the given path.
+fn read_redirect_table(map: &RedirectMap, title_map: &TitleMap, path: &Path) -> anyhow::Result<()> {
+    let file = std::fs::File::open(path)?;
+    let progress_bar =
+        file_progress_bar(file.metadata()?.len()).with_message("Reading redirect table");
+    let mut reader = BufReader::new(GzDecoder::new(BufReader::new(progress_bar.wrap_read(file))));
+
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+        let line = String::from_utf8_lossy(&buf);
+        let Some(tuples) = parse_insert_values(line.trim_end())? else {
+            continue;
+        };
+
+        // The columns are `rd_from`, `rd_namespace`, `rd_title`, `rd_interwiki` and `rd_fragment`.
+        for tuple in tuples {
+            let (Some(from), Some(namespace), Some(title)) = (
+                tuple.first().and_then(|value| value.as_number::<u32>()),
+                tuple.get(1).and_then(|value| value.as_number::<i32>()),
+                tuple.get(2).and_then(|value| value.as_str()),
+            ) else {
+                continue;
+            };
+            // The redirect table also contains pages that aren't in the articles dump, such as talk pages.
+            if title_map.get_title(from).is_none() {
+                continue;
+            }
+
+            let is_interwiki = tuple
+                .get(3)
+                .and_then(|value| value.as_str())
+                .is_some_and(|interwiki| !interwiki.is_empty());
+            let target = if is_interwiki {
+                None
+            } else {
+                title_map.get_id_in_namespace(namespace, title)
+            };
+            map.insert(from, target);
+        }
+    }
+
+    progress_bar.finish();
+    Ok(())
+}
+
+/// The result of repeatedly following redirects from a given page.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RedirectResolution {
+    /// The chain of redirects ended at this page, which is not a redirect.
+    Target(u32),
+    /// The chain of redirects ended at a redirect whose target does not exist.
+    Dangling(u32),
+    /// The chain of redirects entered a cycle, consisting of these pages in order.
+    Cycle(Vec<u32>),
+}
+
+/// Follows redirects starting at `id` until we reach a page that is not a redirect.
+/// This never loops forever: we keep track of the pages we've visited so that cycles can be detected.
+pub fn resolve_redirect(redirects: &RedirectMap, id: u32) -> RedirectResolution {
+    let mut chain = vec![id];
+    loop {
+        let current = *chain.last().unwrap();
+        match redirects.with(&current, |target| *target) {
+            None => return RedirectResolution::Target(current),
+            Some(None) => return RedirectResolution::Dangling(current),
+            Some(Some(target)) => match chain.iter().position(|id| *id == target) {
+                Some(index) => return RedirectResolution::Cycle(chain.split_off(index)),
+                None => chain.push(target),
+            },
+        }
+    }
+}
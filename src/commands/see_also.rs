@@ -0,0 +1,25 @@
+use super::links::generate_outgoing_links_by_section;
+use crate::titles::generate_title_map;
+
+/// The heading of the section listing related articles, which editors curate by hand.
+const SEE_ALSO: &str = "See also";
+
+/// Lists the articles linked from the "See also" section of an article.
+pub fn execute(article: String, ignore_case: bool) -> anyhow::Result<()> {
+    let title_map = generate_title_map(false)?;
+    let outgoing_links = generate_outgoing_links_by_section(false)?;
+
+    let id = title_map.resolve(&article, ignore_case)?;
+    let links = outgoing_links
+        .with(&id, |links| links.clone())
+        .unwrap_or_default();
+    for (link, _) in links.iter().filter(|(_, section)| {
+        section
+            .as_deref()
+            .is_some_and(|section| section.eq_ignore_ascii_case(SEE_ALSO))
+    }) {
+        println!("> {}", title_map.get_title(*link).unwrap());
+    }
+
+    Ok(())
+}
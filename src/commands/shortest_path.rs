@@ -1,31 +1,197 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Arc, Mutex},
+};
 
+use clap::{Args, ValueEnum};
 use console::style;
 
 use crate::{
     hierarchical_map::HierarchicalMap,
-    titles::{canonicalise_wikilink, generate_title_map},
+    titles::{generate_list_articles, generate_non_articles, generate_title_map, TitleMap},
+};
+
+use super::{
+    links::{
+        generate_in_degree, generate_incoming_links, generate_out_degree, generate_outgoing_links,
+        redirects_to_resolve, resolve_link, DegreeMap,
+    },
+    redirects::generate_redirects,
 };
 
-use super::links::{generate_incoming_links, generate_outgoing_links};
+/// Once expanding the smaller frontier is estimated to look at more than this many links,
+/// we instead expand whichever side is estimated to look at fewer links.
+const ADAPTIVE_THRESHOLD: u64 = 100_000;
+
+/// If the end article has more incoming links than this, [`SearchStrategy::Auto`] only searches forwards,
+/// since searching backwards from such a hub would immediately produce an enormous frontier.
+const HUB_IN_DEGREE: u32 = 50_000;
+
+/// The levels discovered by a search from one end,
+/// where the `n`th level maps each article at distance `n` to how it was found.
+type Levels = Vec<HashMap<u32, Discovery>>;
+
+/// How the search reached an article.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Discovery {
+    /// The article in the previous level through which this article was first found.
+    pub predecessor: u32,
+    /// The number of minimal paths between this article and the end of the search it was found from.
+    pub paths: u64,
+}
+
+impl Discovery {
+    /// The discovery of the article at either end of the search, which is its own only minimal path.
+    const ROOT: Self = Discovery {
+        predecessor: 0,
+        paths: 1,
+    };
+}
+
+/// How the solver searches for a path.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SearchStrategy {
+    /// Searches forwards only if the end article is a hub, and in both directions otherwise
+    #[default]
+    Auto,
+    /// Searches forwards from the start and backwards from the end
+    Bidirectional,
+    /// Searches forwards from the start only
+    Forward,
+}
+
+/// Kinds of articles that a path may be forbidden from passing through.
+#[derive(Debug, Clone, Default, Args)]
+pub struct Exclusions {
+    /// Forbids the path from passing through list articles, such as `List of ...` and `Index of ...`
+    #[arg(long)]
+    pub no_lists: bool,
+    /// Forbids the path from passing through pages outside the main namespace, such as categories and portals
+    #[arg(long)]
+    pub articles_only: bool,
+    /// Forbids the path from passing through this article; may be given several times
+    #[arg(long, value_name = "TITLE", value_parser = crate::titles::parse_article_title)]
+    pub avoid: Vec<String>,
+}
+
+impl Exclusions {
+    /// Returns the IDs of the articles that the path may not pass through, reporting how many there are of each kind.
+    /// The titles of articles to avoid are resolved using the title map, as for the start and end articles.
+    pub fn forbidden(
+        &self,
+        title_map: &TitleMap,
+        ignore_case: bool,
+    ) -> anyhow::Result<HashSet<u32>> {
+        let mut forbidden = HashSet::new();
+        if self.no_lists {
+            let list_articles = generate_list_articles()?;
+            println!(
+                "Excluding {} list articles",
+                style(list_articles.len()).bold().bright()
+            );
+            forbidden.extend(list_articles);
+        }
+        if self.articles_only {
+            let non_articles = generate_non_articles()?;
+            println!(
+                "Excluding {} pages outside the main namespace",
+                style(non_articles.len()).bold().bright()
+            );
+            forbidden.extend(non_articles);
+        }
+        if !self.avoid.is_empty() {
+            // Several titles may resolve to the same article, so we count the articles rather than the titles.
+            let avoided = self
+                .avoid
+                .iter()
+                .map(|title| title_map.resolve(title, ignore_case))
+                .collect::<anyhow::Result<HashSet<_>>>()?;
+            println!(
+                "Excluding {} articles to avoid",
+                style(avoided.len()).bold().bright()
+            );
+            forbidden.extend(avoided);
+        }
+        Ok(forbidden)
+    }
+}
 
-pub fn execute(start: String, end: String) -> anyhow::Result<()> {
+/// Finds a minimal path between two articles.
+/// If `levels` is true, we also report how many articles were found at each depth from either end.
+/// The path may not pass through any of the articles given by the `exclusions`.
+/// If `redirects_free` is true, following a link to a redirect page does not count towards the degree of the path.
+/// If `count` is true, we also report how many minimal paths there are.
+#[allow(clippy::too_many_arguments)]
+pub fn execute(
+    start: String,
+    end: String,
+    ignore_case: bool,
+    levels: bool,
+    strategy: SearchStrategy,
+    exclusions: Exclusions,
+    redirects_free: bool,
+    count: bool,
+) -> anyhow::Result<()> {
     let title_map = generate_title_map(false)?;
-    let outgoing_links = generate_outgoing_links(false)?;
-    let incoming_links = generate_incoming_links(false)?;
+    let start = title_map.resolve(&start, ignore_case)?;
+    let end = title_map.resolve(&end, ignore_case)?;
+
+    let forbidden = exclusions.forbidden(&title_map, ignore_case)?;
+
+    if redirects_free {
+        let redirects = redirect_ids()?;
+        let outgoing_links = generate_outgoing_links(false)?;
+        let path = find_path_redirects_free(start, end, &outgoing_links, &redirects, &forbidden);
+        print_path(&title_map, path, &redirects);
+        return Ok(());
+    }
+
+    let (solver, path) = find_path(start, end, strategy, forbidden, true)?;
+    let path = path.map(|path| {
+        let degree = path.len() - 1;
+        (path, degree)
+    });
+    let found = path.is_some();
+    print_path(&title_map, path, &HashSet::new());
+
+    if count && found {
+        println!(
+            "There are {} minimal paths of this degree",
+            style(solver.count_shortest_paths()).bold().bright()
+        );
+    }
+
+    if levels {
+        println!();
+        for (name, levels) in [
+            ("start", solver.forward_levels()),
+            ("end", solver.backward_levels()),
+        ] {
+            for (depth, level) in levels.iter().enumerate() {
+                println!(
+                    "At depth {depth} from the {name} there are {} articles",
+                    style(level.len()).bold().bright()
+                );
+            }
+        }
+    }
 
-    let start = title_map.get_id(&canonicalise_wikilink(&start)).unwrap();
-    let end = title_map.get_id(&canonicalise_wikilink(&end)).unwrap();
+    Ok(())
+}
 
-    let path = Solver::new(start, end).solve(&outgoing_links, &incoming_links, true);
+/// Prints the path and its degree, marking the steps that are redirects.
+fn print_path(title_map: &TitleMap, path: Option<(Vec<u32>, usize)>, redirects: &HashSet<u32>) {
     match path {
-        Some(path) => {
+        Some((path, degree)) => {
             println!(
                 "\nMinimal path of degree {} found!",
-                style(path.len() - 1).bold().bright()
+                style(degree).bold().bright()
             );
             for (i, item) in path.iter().enumerate() {
-                let title = title_map.get_title(*item).unwrap();
+                let mut title = title_map.get_title(*item).unwrap();
+                if redirects.contains(item) {
+                    title = format!("{title} {}", style("(redirect)").dim());
+                }
                 if i == 0 {
                     println!("{} {}", style("start").red(), title);
                 } else if i == path.len() - 1 {
@@ -39,68 +205,236 @@ pub fn execute(start: String, end: String) -> anyhow::Result<()> {
             println!("\nNo path exists.");
         }
     }
+}
 
-    Ok(())
+/// Returns the IDs of every redirect page.
+fn redirect_ids() -> anyhow::Result<HashSet<u32>> {
+    let redirects = generate_redirects(false)?;
+    let rx = redirects.with_all_from_disk("Collecting redirects".to_owned(), |id, _| *id);
+    let mut ids = HashSet::new();
+    while let Ok(id) = rx.recv() {
+        ids.insert(id?);
+    }
+    Ok(ids)
+}
+
+/// Finds a path from the start article to the end article that follows as few links as possible,
+/// where links to redirect pages are free, since a redirect is just another name for its target.
+/// Returns the path, which still includes the redirect pages, together with its degree.
+///
+/// Since links now cost either zero or one, a breadth-first search no longer finds minimal paths.
+/// Instead, we run a 0-1 breadth-first search forwards from the start article:
+/// articles reached through a free link are pushed to the front of the queue rather than the back,
+/// so articles are still visited in order of their distance from the start.
+/// The bidirectional search of [`Solver`] is not used, so this may explore many more articles.
+pub fn find_path_redirects_free(
+    start: u32,
+    end: u32,
+    outgoing_links: &HierarchicalMap<u8, u32, Vec<u32>>,
+    redirects: &HashSet<u32>,
+    forbidden: &HashSet<u32>,
+) -> Option<(Vec<u32>, usize)> {
+    let mut distances = HashMap::from([(start, 0)]);
+    let mut predecessors = HashMap::new();
+    let mut queue = VecDeque::from([(start, 0)]);
+    while let Some((id, distance)) = queue.pop_front() {
+        if distance > distances[&id] {
+            // We already found a shorter route to this article.
+            continue;
+        }
+        if id == end {
+            let mut path = vec![end];
+            while let Some(predecessor) = predecessors.get(path.last().unwrap()) {
+                path.push(*predecessor);
+            }
+            path.reverse();
+            return Some((path, distance));
+        }
+        for link in outgoing_links
+            .with(&id, |links| links.clone())
+            .into_iter()
+            .flatten()
+        {
+            if link != end && forbidden.contains(&link) {
+                continue;
+            }
+            let free = redirects.contains(&link);
+            let link_distance = if free { distance } else { distance + 1 };
+            if distances
+                .get(&link)
+                .is_none_or(|current| link_distance < *current)
+            {
+                distances.insert(link, link_distance);
+                predecessors.insert(link, id);
+                if free {
+                    queue.push_front((link, link_distance));
+                } else {
+                    queue.push_back((link, link_distance));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Loads the link maps and searches for a minimal path between the two articles,
+/// which may not pass through any `forbidden` article.
+/// If links to redirects are resolved, a redirect at either end is replaced by the page it redirects to.
+/// Returns the solver, so that the levels explored during the search can be inspected, together with the path.
+pub fn find_path(
+    start: u32,
+    end: u32,
+    strategy: SearchStrategy,
+    forbidden: HashSet<u32>,
+    print_progress: bool,
+) -> anyhow::Result<(Solver, Option<Vec<u32>>)> {
+    let outgoing_links = generate_outgoing_links(false)?;
+    let incoming_links = generate_incoming_links(false)?;
+    let out_degree = generate_out_degree(false)?;
+    let in_degree = generate_in_degree(false)?;
+
+    let redirects = redirects_to_resolve(false)?;
+    let start = resolve_link(redirects.as_ref(), start);
+    let end = resolve_link(redirects.as_ref(), end);
+
+    let mut solver = Solver::new(start, end)
+        .with_degrees(out_degree, in_degree)
+        .with_strategy(strategy)
+        .with_forbidden(forbidden);
+    let path = solver.solve(&outgoing_links, &incoming_links, None, print_progress);
+    Ok((solver, path))
 }
 
 pub struct Solver {
-    /// The `n`th entry maps IDs `id` of "rank `n`" to IDs of "rank `n - 1`" that have a link to `id`.
-    /// By convention, the `0`th entry consists of the single pair `(start, 0)` where `start` is the start article.
+    /// The `n`th entry maps IDs `id` of "rank `n`" to an ID of "rank `n - 1`" that has a link to `id`,
+    /// together with the number of minimal paths from the start article to `id`.
+    /// By convention, the `0`th entry consists of the single article `start` with predecessor `0`.
     /// Once `start` and `end` meet in the middle, we can use their data to reconstruct the full path.
-    start: Vec<HashMap<u32, u32>>,
-    /// The `n`th entry maps IDs `id` of "rank `n`" to IDs of "rank `n - 1`" that `id` links to.
-    /// By convention, the `0`th entry consists of the single pair `(end, 0)` where `end` is the end article.
+    start: Levels,
+    /// The `n`th entry maps IDs `id` of "rank `n`" to an ID of "rank `n - 1`" that `id` links to,
+    /// together with the number of minimal paths from `id` to the end article.
+    /// By convention, the `0`th entry consists of the single article `end` with predecessor `0`.
     /// Once `start` and `end` meet in the middle, we can use their data to reconstruct the full path.
-    end: Vec<HashMap<u32, u32>>,
+    end: Levels,
+    /// The out-degree and in-degree maps, used to estimate the cost of expanding each frontier.
+    /// If these are not supplied, we always expand the smaller frontier.
+    degrees: Option<(DegreeMap, DegreeMap)>,
+    strategy: SearchStrategy,
+    /// Articles that the path may not pass through.
+    forbidden: HashSet<u32>,
 }
 
 impl Solver {
     pub fn new(start: u32, end: u32) -> Self {
         Self {
-            start: vec![{
-                let mut result = HashMap::new();
-                result.insert(start, 0);
-                result
-            }],
-            end: vec![{
-                let mut result = HashMap::new();
-                result.insert(end, 0);
-                result
-            }],
+            start: vec![HashMap::from([(start, Discovery::ROOT)])],
+            end: vec![HashMap::from([(end, Discovery::ROOT)])],
+            degrees: None,
+            strategy: SearchStrategy::default(),
+            forbidden: HashSet::new(),
         }
     }
 
-    fn populate_forward(&mut self, outgoing_links: &HierarchicalMap<u8, u32, Vec<u32>>) {
-        let mut new_map = HashMap::new();
-        for id in self.start.last().unwrap().keys() {
-            for link in outgoing_links
-                .with(id, |links| links.clone())
-                .into_iter()
-                .flatten()
-            {
-                // Because of how we conduct the search, we don't need to re-add articles we've already looked at.
-                if !self.start.iter().any(|map| map.contains_key(&link)) {
-                    new_map.insert(link, *id);
-                }
+    /// Creates a solver that carries on from levels previously discovered by searching forwards from the start article,
+    /// as returned by [`Solver::forward_levels`].
+    fn from_forward_levels(mut levels: Levels, end: u32) -> Self {
+        // The search only looks for the end article in the last level of each side,
+        // so if we already found the end article we discard the levels after it.
+        if let Some(depth) = levels.iter().position(|level| level.contains_key(&end)) {
+            levels.truncate(depth + 1);
+        }
+        Self {
+            start: levels,
+            ..Self::new(0, end)
+        }
+    }
+
+    /// Uses the given degree maps to choose which frontier to expand when the frontiers get large.
+    pub fn with_degrees(mut self, out_degree: DegreeMap, in_degree: DegreeMap) -> Self {
+        self.degrees = Some((out_degree, in_degree));
+        self
+    }
+
+    /// Sets the search strategy.
+    /// [`SearchStrategy::Auto`] can only choose to search forwards if the degree maps were supplied.
+    pub fn with_strategy(mut self, strategy: SearchStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Forbids the path from passing through the given articles.
+    /// The start and end articles are always allowed, even if they are in this set.
+    pub fn with_forbidden(mut self, mut forbidden: HashSet<u32>) -> Self {
+        for id in self.start[0].keys().chain(self.end[0].keys()) {
+            forbidden.remove(id);
+        }
+        self.forbidden = forbidden;
+        self
+    }
+
+    /// Decides whether to search only forwards, resolving [`SearchStrategy::Auto`] using the degree maps.
+    fn forward_only(&self) -> bool {
+        match self.strategy {
+            SearchStrategy::Auto => {
+                let Some((_, in_degree)) = &self.degrees else {
+                    return false;
+                };
+                let end = self.end[0].keys().next().unwrap();
+                in_degree.with(end, |degree| *degree).unwrap_or(0) > HUB_IN_DEGREE
             }
+            SearchStrategy::Bidirectional => false,
+            SearchStrategy::Forward => true,
+        }
+    }
+
+    /// The articles discovered by searching forwards from the start article.
+    /// The `n`th level maps each article at distance `n` from the start to how it was found.
+    pub fn forward_levels(&self) -> &[HashMap<u32, Discovery>] {
+        &self.start
+    }
+
+    /// The articles discovered by searching backwards from the end article.
+    /// The `n`th level maps each article at distance `n` to the end to how it was found.
+    pub fn backward_levels(&self) -> &[HashMap<u32, Discovery>] {
+        &self.end
+    }
+
+    /// Returns true if we should expand the start frontier rather than the end frontier.
+    ///
+    /// Normally we expand the smaller frontier, but for pairs of articles near hubs,
+    /// a small frontier can still have a huge number of links.
+    /// So if expanding the smaller frontier would look at too many links,
+    /// we expand the side that would look at fewer links, as estimated by the degree maps.
+    fn should_expand_forward(&self) -> bool {
+        let start_frontier = self.start.last().unwrap();
+        let end_frontier = self.end.last().unwrap();
+        let forward = start_frontier.len() <= end_frontier.len();
+
+        let Some((out_degree, in_degree)) = &self.degrees else {
+            return forward;
+        };
+        let cost = |frontier: &HashMap<u32, Discovery>, degree: &DegreeMap| {
+            frontier
+                .keys()
+                .map(|id| degree.with(id, |degree| *degree as u64).unwrap_or(0))
+                .sum::<u64>()
+        };
+        let forward_cost = cost(start_frontier, out_degree);
+        let backward_cost = cost(end_frontier, in_degree);
+        if (if forward { forward_cost } else { backward_cost }) > ADAPTIVE_THRESHOLD {
+            forward_cost <= backward_cost
+        } else {
+            forward
         }
+    }
+
+    fn populate_forward(&mut self, outgoing_links: &HierarchicalMap<u8, u32, Vec<u32>>) {
+        let new_map = expand_level(&self.start, &self.forbidden, outgoing_links);
         self.start.push(new_map);
     }
 
     fn populate_backward(&mut self, incoming_links: &HierarchicalMap<u8, u32, Vec<u32>>) {
-        let mut new_map = HashMap::new();
-        for id in self.end.last().unwrap().keys() {
-            for link in incoming_links
-                .with(id, |links| links.clone())
-                .into_iter()
-                .flatten()
-            {
-                if !self.end.iter().any(|map| map.contains_key(&link)) {
-                    new_map.insert(link, *id);
-                }
-                new_map.insert(link, *id);
-            }
-        }
+        let new_map = expand_level(&self.end, &self.forbidden, incoming_links);
         self.end.push(new_map);
     }
 
@@ -111,34 +445,60 @@ impl Solver {
 
         if let Some(connection) = start_map.keys().find(|key| end_map.contains_key(key)) {
             // We found a path.
-            let mut path = Vec::new();
+            // Each rank above zero records the predecessor of its articles in the rank below,
+            // so we follow the predecessors down to the start and end articles at rank zero.
+            let mut path = VecDeque::from([*connection]);
             let mut towards_start = *connection;
-            let mut start_rank = self.start.len() - 1;
-            // The two different conditions here protect against off-by-one errors.
-            while towards_start != 0 {
-                path.insert(0, towards_start);
-                towards_start = self.start[start_rank][&towards_start];
-                start_rank -= 1;
+            for level in self.start[1..].iter().rev() {
+                towards_start = level[&towards_start].predecessor;
+                path.push_front(towards_start);
             }
             let mut towards_end = *connection;
-            let mut end_rank = self.end.len() - 1;
-            while end_rank != 0 {
-                towards_end = self.end[end_rank][&towards_end];
-                path.push(towards_end);
-                end_rank -= 1;
+            for level in self.end[1..].iter().rev() {
+                towards_end = level[&towards_end].predecessor;
+                path.push_back(towards_end);
             }
-            Some(path)
+            Some(path.into())
         } else {
             None
         }
     }
 
+    /// Counts the minimal paths from the start article to the end article found by [`Solver::solve`],
+    /// without listing them, or returns zero if no path was found.
+    ///
+    /// Each minimal path passes through exactly one article where the two frontiers meet,
+    /// so we add up the number of ways to reach each such article from the start and to leave it for the end.
+    /// The count saturates at [`u64::MAX`].
+    pub fn count_shortest_paths(&self) -> u64 {
+        let start_map = self.start.last().unwrap();
+        let end_map = self.end.last().unwrap();
+        start_map
+            .iter()
+            .filter_map(|(id, forward)| {
+                let backward = end_map.get(id)?;
+                Some(forward.paths.saturating_mul(backward.paths))
+            })
+            .fold(0, u64::saturating_add)
+    }
+
+    /// Finds a minimal path from the start article to the end article, if one exists.
+    /// The levels explored during the search remain available afterwards,
+    /// through [`Solver::forward_levels`] and [`Solver::backward_levels`].
+    ///
+    /// If `max_depth` is given, we give up and return [`None`] once every path of at most `max_depth` links
+    /// has been ruled out, rather than carrying on expanding ever larger frontiers.
     pub fn solve(
-        mut self,
+        &mut self,
         outgoing_links: &HierarchicalMap<u8, u32, Vec<u32>>,
         incoming_links: &HierarchicalMap<u8, u32, Vec<u32>>,
+        max_depth: Option<usize>,
         print_progress: bool,
     ) -> Option<Vec<u32>> {
+        let forward_only = self.forward_only();
+        if print_progress && forward_only {
+            println!("Searching forwards only");
+        }
         loop {
             if print_progress {
                 println!(
@@ -168,7 +528,16 @@ impl Solver {
                 return Some(path);
             }
 
-            if self.start.last().unwrap().len() <= self.end.last().unwrap().len() {
+            // Any path we find after the next expansion has one more link than the levels so far allow.
+            if max_depth.is_some_and(|max_depth| self.start.len() + self.end.len() - 2 >= max_depth)
+            {
+                if print_progress {
+                    println!("Reached the maximum depth");
+                }
+                return None;
+            }
+
+            if forward_only || self.should_expand_forward() {
                 if print_progress {
                     println!("Populating forward");
                 }
@@ -182,3 +551,191 @@ impl Solver {
         }
     }
 }
+
+/// Finds the next level of a search from the last of the given `levels`, following the given links.
+/// Each new article counts the minimal paths through every article in the last level that links to it.
+/// Because of how we conduct the search, we don't need to re-add articles found at earlier levels.
+fn expand_level(
+    levels: &Levels,
+    forbidden: &HashSet<u32>,
+    links: &HierarchicalMap<u8, u32, Vec<u32>>,
+) -> HashMap<u32, Discovery> {
+    let mut new_map = HashMap::<u32, Discovery>::new();
+    for (id, discovery) in levels.last().unwrap() {
+        for link in links.with(id, |links| links.clone()).into_iter().flatten() {
+            if forbidden.contains(&link) || levels.iter().any(|map| map.contains_key(&link)) {
+                continue;
+            }
+            new_map
+                .entry(link)
+                .and_modify(|existing| {
+                    existing.paths = existing.paths.saturating_add(discovery.paths)
+                })
+                .or_insert(Discovery {
+                    predecessor: *id,
+                    paths: discovery.paths,
+                });
+        }
+    }
+    new_map
+}
+
+/// How many random end articles to try for each random start article when sampling paths,
+/// so that searches from the same start article can reuse its levels through a [`SolverCache`].
+pub const ENDS_PER_START: usize = 16;
+
+/// Remembers the levels discovered by searching forwards from recently used start articles,
+/// so that later searches from the same start article can carry on from where they left off.
+/// The link maps never change while the program is running, so entries never need to be invalidated.
+pub struct SolverCache {
+    capacity: usize,
+    /// The levels discovered from each start article, with the most recently used last.
+    entries: Mutex<VecDeque<(u32, Arc<Levels>)>>,
+}
+
+impl SolverCache {
+    /// Creates a cache that remembers the levels of at most `capacity` start articles.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Creates a solver between the two articles, reusing any levels cached for the start article.
+    pub fn solver(&self, start: u32, end: u32) -> Solver {
+        let levels = {
+            let mut entries = self.entries.lock().unwrap();
+            entries
+                .iter()
+                .position(|(id, _)| *id == start)
+                .and_then(|index| entries.remove(index))
+                .map(|entry| {
+                    let levels = Arc::clone(&entry.1);
+                    entries.push_back(entry);
+                    levels
+                })
+        };
+        match levels {
+            Some(levels) => Solver::from_forward_levels((*levels).clone(), end),
+            None => Solver::new(start, end),
+        }
+    }
+
+    /// Records the levels discovered by the solver, if they go deeper than the levels already cached.
+    pub fn record(&self, solver: &Solver) {
+        let levels = solver.forward_levels();
+        let start = *levels[0].keys().next().unwrap();
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(index) = entries.iter().position(|(id, _)| *id == start) {
+            if entries[index].1.len() >= levels.len() {
+                return;
+            }
+            entries.remove(index);
+        }
+        entries.push_back((start, Arc::new(levels.to_vec())));
+        while entries.len() > self.capacity {
+            entries.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::titles::id_short_key;
+
+    type LinkMap = HierarchicalMap<u8, u32, Vec<u32>>;
+
+    /// Builds fully loaded outgoing and incoming link maps from a list of links.
+    fn link_maps(links: &[(u32, u32)]) -> (LinkMap, LinkMap) {
+        let outgoing = HierarchicalMap::new(PathBuf::from("outgoing"), id_short_key);
+        let incoming = HierarchicalMap::new(PathBuf::from("incoming"), id_short_key);
+        for &(from, to) in links {
+            outgoing.mutate_with_default(from, |links: &mut Vec<u32>| links.push(to));
+            incoming.mutate_with_default(to, |links: &mut Vec<u32>| links.push(from));
+        }
+        outgoing.mark_loaded();
+        incoming.mark_loaded();
+        (outgoing, incoming)
+    }
+
+    #[test]
+    fn backward_search_keeps_the_earliest_rank() {
+        // Article 3 links to the end article 1 both directly and through article 2,
+        // so it is reachable at backward ranks 1 and 2.
+        let (outgoing, incoming) = link_maps(&[(4, 3), (3, 2), (3, 1), (2, 1)]);
+        let mut solver = Solver::new(4, 1);
+        solver.populate_backward(&incoming);
+        solver.populate_backward(&incoming);
+        let levels = solver.backward_levels();
+        assert_eq!(levels[1][&3].predecessor, 1);
+        assert!(!levels[2].contains_key(&3));
+        assert_eq!(solver.complete_path(), Some(vec![4, 3, 1]));
+
+        let mut solver = Solver::new(4, 1).with_strategy(SearchStrategy::Bidirectional);
+        let path = solver.solve(&outgoing, &incoming, None, false);
+        assert_eq!(path, Some(vec![4, 3, 1]));
+        assert_eq!(solver.count_shortest_paths(), 1);
+    }
+
+    /// A small graph in which some pairs of articles are joined by several minimal paths,
+    /// some by paths of different lengths, and some not at all.
+    const GRAPH: [(u32, u32); 14] = [
+        (1, 2),
+        (1, 3),
+        (2, 4),
+        (3, 4),
+        (4, 5),
+        (5, 6),
+        (2, 7),
+        (7, 8),
+        (8, 6),
+        (6, 1),
+        (9, 1),
+        (5, 9),
+        (3, 10),
+        (10, 5),
+    ];
+
+    #[test]
+    fn forward_search_agrees_with_bidirectional_search() {
+        let (outgoing, incoming) = link_maps(&GRAPH);
+        let links = GRAPH.iter().copied().collect::<HashSet<_>>();
+        for start in 1..=10 {
+            for end in 1..=10 {
+                let mut results = Vec::new();
+                for strategy in [SearchStrategy::Forward, SearchStrategy::Bidirectional] {
+                    let mut solver = Solver::new(start, end).with_strategy(strategy);
+                    let path = solver.solve(&outgoing, &incoming, None, false);
+                    if let Some(path) = &path {
+                        assert_eq!((path[0], path[path.len() - 1]), (start, end));
+                        assert!(path
+                            .windows(2)
+                            .all(|link| links.contains(&(link[0], link[1]))));
+                    }
+                    results.push((path.map(|path| path.len()), solver.count_shortest_paths()));
+                }
+                assert_eq!(results[0], results[1], "from {start} to {end}");
+            }
+        }
+    }
+
+    #[test]
+    fn forbidden_articles_are_avoided() {
+        // The only minimal path from 1 to 3 passes through 2, and a longer one passes through 4 and 5.
+        let (outgoing, incoming) = link_maps(&[(1, 2), (2, 3), (1, 4), (4, 5), (5, 3)]);
+        let solve = |forbidden: &[u32]| {
+            Solver::new(1, 3)
+                .with_forbidden(forbidden.iter().copied().collect())
+                .solve(&outgoing, &incoming, None, false)
+        };
+        assert_eq!(solve(&[]), Some(vec![1, 2, 3]));
+        assert_eq!(solve(&[2]), Some(vec![1, 4, 5, 3]));
+        assert_eq!(solve(&[2, 5]), None);
+        // The start and end articles are always allowed.
+        assert_eq!(solve(&[1, 3]), Some(vec![1, 2, 3]));
+    }
+}
@@ -0,0 +1,167 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use console::style;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    memoise::memoise,
+    stats::{HistogramSummary, StreamingHistogram},
+    titles::{generate_title_map, TitleMap},
+};
+
+use super::{
+    links::{generate_in_degree, generate_out_degree, is_in_link_graph, link_map_prefix},
+    redirects::generate_redirects,
+};
+
+/// The number of articles with the highest degrees that are cached, which bounds the `top` argument.
+pub const MAX_TOP: usize = 100;
+
+/// Summary statistics of the link graph.
+#[derive(Debug, Serialize, Deserialize)]
+struct GraphStats {
+    /// The number of pages in the link graph that are not redirects.
+    articles: u64,
+    redirects: u64,
+    /// The number of links in the graph, including links from redirects.
+    links: u64,
+    /// The number of articles with no outgoing or incoming links.
+    isolated: u64,
+    /// The out-degrees of articles, not including redirects.
+    out_degree: HistogramSummary,
+    /// The in-degrees of articles, not including redirects.
+    in_degree: HistogramSummary,
+    /// The articles with the highest out-degrees, as `(id, degree)` pairs in decreasing order of degree.
+    top_out_degree: Vec<(u32, u32)>,
+    /// The articles with the highest in-degrees, as `(id, degree)` pairs in decreasing order of degree.
+    top_in_degree: Vec<(u32, u32)>,
+}
+
+/// Reports the number of articles and links in the link graph, how their degrees are distributed,
+/// and the `top` articles with the most outgoing and incoming links.
+pub fn execute(top: usize) -> anyhow::Result<()> {
+    let stats = memoise(
+        &link_map_prefix("graph_stats").display().to_string(),
+        "Computing link graph statistics",
+        false,
+        generate_graph_stats,
+    )?;
+    let title_map = generate_title_map(false)?;
+
+    println!(
+        "The link graph has {} articles and {} redirects, with {} links between them",
+        style(stats.articles).bold().bright(),
+        style(stats.redirects).bold().bright(),
+        style(stats.links).bold().bright()
+    );
+    println!(
+        "{} articles have no links to or from them",
+        style(stats.isolated).bold().bright()
+    );
+    println!("Out-degree: {}", stats.out_degree);
+    println!("In-degree: {}", stats.in_degree);
+
+    for (direction, top_degree) in [
+        ("outgoing", &stats.top_out_degree),
+        ("incoming", &stats.top_in_degree),
+    ] {
+        println!("\nArticles with the most {direction} links:");
+        print_top(&title_map, &top_degree[..top.min(top_degree.len())]);
+    }
+
+    Ok(())
+}
+
+fn print_top(title_map: &TitleMap, top_degree: &[(u32, u32)]) {
+    for (i, (id, degree)) in top_degree.iter().enumerate() {
+        println!(
+            "{:>5} {} ({} links)",
+            style(format!("{}.", i + 1)).dim(),
+            title_map
+                .get_title(*id)
+                .unwrap_or_else(|| format!("<id {id}>")),
+            style(degree).bold().bright()
+        );
+    }
+}
+
+fn generate_graph_stats() -> anyhow::Result<GraphStats> {
+    // We look up every article in each of these maps, so we load them fully,
+    // rather than binary searching their buckets on disk millions of times.
+    // Lookups in a fully loaded map never touch the disk, so they can't fail.
+    let title_map = generate_title_map(true)?;
+    let out_degree = generate_out_degree(true)?;
+    let in_degree = generate_in_degree(true)?;
+    let redirects = generate_redirects(true)?;
+
+    let mut articles = 0;
+    let mut redirect_count = 0;
+    let mut links = 0;
+    let mut isolated = 0;
+    let mut out_histogram = StreamingHistogram::new();
+    let mut in_histogram = StreamingHistogram::new();
+    let mut top_out = TopDegrees::default();
+    let mut top_in = TopDegrees::default();
+
+    // We iterate over the title map rather than the degree maps,
+    // so that articles with no links at all are counted too.
+    let rx = title_map.with_all_titles("Collecting degrees".to_owned());
+    while let Ok((id, _)) = rx.recv() {
+        if !is_in_link_graph(id) {
+            continue;
+        }
+        let out = out_degree.with(&id, |degree| *degree).unwrap_or(0);
+        links += out as u64;
+        if redirects.contains_key(&id)? {
+            redirect_count += 1;
+            continue;
+        }
+
+        let incoming = in_degree.with(&id, |degree| *degree).unwrap_or(0);
+        articles += 1;
+        if out == 0 && incoming == 0 {
+            isolated += 1;
+        }
+        out_histogram.add(out as u64);
+        in_histogram.add(incoming as u64);
+        top_out.add(id, out);
+        top_in.add(id, incoming);
+    }
+
+    Ok(GraphStats {
+        articles,
+        redirects: redirect_count,
+        links,
+        isolated,
+        out_degree: out_histogram.summary(),
+        in_degree: in_histogram.summary(),
+        top_out_degree: top_out.into_sorted_vec(),
+        top_in_degree: top_in.into_sorted_vec(),
+    })
+}
+
+/// Keeps track of the [`MAX_TOP`] articles with the highest degrees seen so far.
+#[derive(Default)]
+struct TopDegrees {
+    /// A min-heap, so that the article with the lowest degree is the one replaced.
+    heap: BinaryHeap<Reverse<(u32, u32)>>,
+}
+
+impl TopDegrees {
+    fn add(&mut self, id: u32, degree: u32) {
+        self.heap.push(Reverse((degree, id)));
+        if self.heap.len() > MAX_TOP {
+            self.heap.pop();
+        }
+    }
+
+    /// Returns the `(id, degree)` pairs in decreasing order of degree.
+    fn into_sorted_vec(self) -> Vec<(u32, u32)> {
+        // Sorting the reversed pairs in increasing order puts the highest degrees first.
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((degree, id))| (id, degree))
+            .collect()
+    }
+}
@@ -0,0 +1,76 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use console::style;
+
+use crate::{progress_bar::normal_progress_bar, titles::generate_title_map};
+
+use super::links::generate_outgoing_links;
+
+/// Runs a breadth-first search from the given article, recording the article through which each article was first
+/// discovered, and writes the resulting spanning tree to `output` as a tab-separated list of `parent<TAB>child` edges.
+/// The search stops after `depth` steps, or once `max_nodes` articles have been discovered.
+pub fn execute(article: String, depth: u32, max_nodes: usize, output: &Path) -> anyhow::Result<()> {
+    let title_map = generate_title_map(false)?;
+    let outgoing_links = generate_outgoing_links(false)?;
+
+    let root = title_map.resolve(&article, false)?;
+
+    // Maps each discovered article to its depth in the tree.
+    let mut discovered = HashMap::new();
+    discovered.insert(root, 0);
+    // The edges `(predecessor, article)` of the tree, in the order they were discovered.
+    let mut edges = Vec::new();
+    let mut queue = VecDeque::from([root]);
+
+    let progress_bar = normal_progress_bar(max_nodes as u64).with_message("Discovering articles");
+    progress_bar.inc(1);
+    'search: while let Some(id) = queue.pop_front() {
+        let id_depth = discovered[&id];
+        if id_depth >= depth {
+            continue;
+        }
+        for link in outgoing_links
+            .with(&id, |links| links.clone())
+            .into_iter()
+            .flatten()
+        {
+            if discovered.len() >= max_nodes {
+                break 'search;
+            }
+            if discovered.contains_key(&link) {
+                continue;
+            }
+            discovered.insert(link, id_depth + 1);
+            edges.push((id, link));
+            queue.push_back(link);
+            progress_bar.inc(1);
+        }
+    }
+    progress_bar.finish_and_clear();
+
+    let mut writer = BufWriter::new(std::fs::File::create(output)?);
+    for (parent, child) in &edges {
+        writeln!(
+            writer,
+            "{}\t{}",
+            title_map.get_title(*parent).unwrap(),
+            title_map.get_title(*child).unwrap()
+        )?;
+    }
+    writer.flush()?;
+
+    println!(
+        "Wrote a tree of {} articles with maximum depth {} to {}",
+        style(discovered.len()).bold().bright(),
+        style(discovered.values().max().unwrap_or(&0))
+            .bold()
+            .bright(),
+        output.display()
+    );
+
+    Ok(())
+}
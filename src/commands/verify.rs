@@ -0,0 +1,101 @@
+use std::{fs::File, io::Read, path::Path};
+
+use console::style;
+use indicatif::MultiProgress;
+
+use crate::{
+    page::read_dump_status,
+    progress_bar::{file_progress_bar, step_progress_bar, ProgressHandle},
+};
+
+use super::download::{is_index, local_path};
+
+/// The outcome of verifying a single file.
+enum Verification {
+    Passed,
+    Failed,
+    Missing,
+    /// Index files are decompressed when they are downloaded, so their published hash no longer applies.
+    Skipped,
+}
+
+/// Recomputes the MD5 hash of each downloaded file of the current dump,
+/// and compares it against the published hash.
+pub fn execute() -> anyhow::Result<()> {
+    let dump_status = read_dump_status()?;
+    let all_files = dump_status.jobs.all_files();
+
+    let multi_progress = MultiProgress::new();
+    let main_progress = step_progress_bar(all_files.len() as u64).with_message("Verifying files");
+    multi_progress.add(main_progress.terminal().clone());
+
+    let mut results = Vec::new();
+    for (file, status) in &all_files {
+        let local_path = local_path(status);
+        let result = if is_index(status) {
+            Verification::Skipped
+        } else if !local_path.is_file() {
+            Verification::Missing
+        } else {
+            let file_progress = file_progress_bar(status.size).with_message(file.clone());
+            multi_progress.add(file_progress.terminal().clone());
+            let digest = md5_file(&local_path, &file_progress)?;
+            multi_progress.remove(file_progress.terminal());
+            if digest == status.md5 {
+                Verification::Passed
+            } else {
+                Verification::Failed
+            }
+        };
+        results.push((file, result));
+        main_progress.inc(1);
+    }
+    main_progress.finish_and_clear();
+
+    let width = results
+        .iter()
+        .map(|(file, _)| file.len())
+        .max()
+        .unwrap_or_default();
+    let mut failed = 0;
+    for (file, result) in results {
+        let result = match result {
+            Verification::Passed => style("ok").green(),
+            Verification::Failed => {
+                failed += 1;
+                style("failed").red()
+            }
+            Verification::Missing => {
+                failed += 1;
+                style("missing").yellow()
+            }
+            Verification::Skipped => style("skipped").dim(),
+        };
+        println!("{file:width$}  {result}");
+    }
+
+    if failed == 0 {
+        Ok(())
+    } else {
+        Err(anyhow::Error::msg(format!(
+            "{failed} files are missing or do not match their published hashes; \
+            run the download command again to replace them"
+        )))
+    }
+}
+
+/// Computes the MD5 hash of the file at the given path.
+fn md5_file(path: &Path, progress: &ProgressHandle) -> anyhow::Result<String> {
+    let mut file = File::open(path)?;
+    let mut context = md5::Context::new();
+    let mut buf = vec![0u8; 0x10000];
+    loop {
+        let bytes_read = file.read(&mut buf)?;
+        if bytes_read == 0 {
+            break;
+        }
+        progress.inc(bytes_read as u64);
+        context.consume(&buf[0..bytes_read]);
+    }
+    Ok(format!("{:x}", context.compute()))
+}
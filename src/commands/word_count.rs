@@ -0,0 +1,52 @@
+use std::path::PathBuf;
+
+use console::style;
+
+use crate::{
+    hierarchical_map::HierarchicalMap,
+    page::{page_stream, StopCondition},
+    parse::wikitext::count_words,
+    titles::{generate_title_map, id_short_key},
+};
+
+pub fn execute(article: String) -> anyhow::Result<()> {
+    let title_map = generate_title_map(false)?;
+    let word_counts = generate_word_counts(false)?;
+
+    let id = title_map.resolve(&article, false)?;
+    match word_counts.with(&id, |count| *count) {
+        Some(count) => println!(
+            "{} has approximately {} words",
+            title_map.get_title(id).unwrap(),
+            style(count).bold().bright()
+        ),
+        None => println!("No word count was recorded for {article}"),
+    }
+
+    Ok(())
+}
+
+/// Computes the approximate number of words in each page, as given by [`count_words`].
+pub fn generate_word_counts(full: bool) -> anyhow::Result<HierarchicalMap<u8, u32, u32>> {
+    let map = HierarchicalMap::new(PathBuf::from("word_counts"), id_short_key);
+    if map.deserialize(full)? {
+        return Ok(map);
+    }
+
+    let stream = page_stream(
+        StopCondition::ALL,
+        1,
+        false,
+        "Counting words".to_string(),
+        |page| (page.id, count_words(&page.revision.text)),
+    )?;
+
+    for (page, count) in stream {
+        map.insert(page, count);
+    }
+
+    map.mark_loaded();
+    map.serialize()?;
+
+    Ok(map)
+}
@@ -0,0 +1,20 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+/// The directory where downloaded dumps and cached data are stored.
+static DATA_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Stores downloaded dumps and cached data in the given directory instead of `data`.
+/// This must be called before anything reads from or writes to the data directory.
+pub fn set_data_dir(path: PathBuf) -> anyhow::Result<()> {
+    DATA_DIR
+        .set(path)
+        .map_err(|_| anyhow::Error::msg("the data directory was already set"))
+}
+
+/// The directory where downloaded dumps and cached data are stored, which is `data` unless configured otherwise.
+pub fn data_dir() -> &'static Path {
+    DATA_DIR.get_or_init(|| PathBuf::from("data"))
+}
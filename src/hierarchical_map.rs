@@ -1,22 +1,36 @@
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     fmt::{Debug, Display},
-    fs::File,
-    io::{BufRead, BufReader, BufWriter, Write},
+    io::{BufRead, BufReader, Read, SeekFrom, Write},
     path::PathBuf,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc, RwLock,
     },
+    time::Instant,
 };
 
 use crossbeam::channel::Receiver;
-use serde::{Deserialize, Serialize};
+use indicatif::{HumanBytes, HumanDuration};
+use serde::{de::IgnoredAny, Deserialize, Serialize};
 
-use crate::{binary_search_line::binary_search_line_in_file, progress_bar};
+use crate::{
+    binary_search_line::binary_search_line_in_file,
+    map_storage::{FileStorage, MapStorage, ReadSeek},
+    memoise::BytesSerde,
+    progress_bar,
+};
 
 type LockedBTreeMap<K, V> = Arc<RwLock<BTreeMap<K, V>>>;
 
+/// Whether to report the time taken and size of each hierarchical map that is fully loaded from disk.
+static LOAD_STATS: AtomicBool = AtomicBool::new(false);
+
+/// Reports the time taken and approximate size of every hierarchical map that is fully loaded from disk.
+pub fn enable_load_stats() {
+    LOAD_STATS.store(true, Ordering::SeqCst);
+}
+
 /// A nested map type, associating values of type `V` to keys of type `L`.
 /// A "short key" of type `K` is derived from each key of type `L`,
 /// and this "short key" is used to partition the main map into many smaller maps,
@@ -34,6 +48,20 @@ pub struct HierarchicalMap<K, L, V> {
     #[allow(clippy::type_complexity)]
     shorten: Arc<Box<dyn Fn(&L) -> K + Send + Sync + 'static>>,
     map: LockedBTreeMap<K, LockedBTreeMap<L, V>>,
+
+    /// Keys removed from this map while it wasn't fully loaded, which may still be serialised on disk.
+    /// Lookups that don't find a key in memory treat these keys as missing rather than checking the disk,
+    /// until their bucket is serialised again.
+    removed: Arc<RwLock<BTreeSet<L>>>,
+
+    /// Where this map is serialised to.
+    storage: Arc<dyn MapStorage>,
+
+    /// How the buckets are encoded in binary, or [`None`] if they are stored as JSON lines.
+    binary: Option<Arc<dyn BinaryCodec<L, V>>>,
+
+    /// The message of the progress bar shown while fully loading this map, or [`None`] to load it silently.
+    load_message: Option<String>,
 }
 
 impl<K, L, V> Clone for HierarchicalMap<K, L, V> {
@@ -43,6 +71,10 @@ impl<K, L, V> Clone for HierarchicalMap<K, L, V> {
             fully_loaded: self.fully_loaded.clone(),
             shorten: self.shorten.clone(),
             map: self.map.clone(),
+            removed: self.removed.clone(),
+            storage: self.storage.clone(),
+            binary: self.binary.clone(),
+            load_message: self.load_message.clone(),
         }
     }
 }
@@ -100,15 +132,80 @@ impl<K, L, V> HierarchicalMap<K, L, V> {
             fully_loaded: Arc::new(AtomicBool::new(false)),
             shorten: Arc::new(Box::new(shorten)),
             map: LockedBTreeMap::default(),
+            removed: Default::default(),
+            storage: Arc::new(FileStorage::default()),
+            binary: None,
+            load_message: None,
         }
     }
 
+    /// Shows a progress bar with the given message, counting the buckets loaded, whenever this map is fully loaded.
+    pub fn with_load_progress(mut self, message: impl Into<String>) -> Self {
+        self.load_message = Some(message.into());
+        self
+    }
+
+    /// Stores the buckets of this map in a compact binary format, instead of as JSON lines.
+    /// This is much smaller and quicker to parse for maps of numbers, such as the link maps.
+    pub fn with_binary_format(mut self) -> Self
+    where
+        L: BytesSerde + 'static,
+        V: BytesSerde + 'static,
+    {
+        self.binary = Some(Arc::new(BytesSerdeCodec));
+        self
+    }
+
+    /// Serialises this map to the given storage instead of the data directory.
+    pub fn with_storage(mut self, storage: Arc<dyn MapStorage>) -> Self {
+        self.storage = storage;
+        self
+    }
+
+    /// The path of the file listing the short keys of this map, relative to the storage root.
+    fn short_keys_path(&self) -> PathBuf {
+        self.prefix.with_extension("json")
+    }
+
+    /// The path of the file containing the inner map for this short key, relative to the storage root.
+    fn bucket_path(&self, short_key: &K) -> PathBuf
+    where
+        K: Display,
+    {
+        self.bucket_path_in_format(short_key, self.binary.is_some())
+    }
+
+    /// The path that the inner map for this short key would have if this map used the other format.
+    /// When a map is regenerated in a different format, we remove these files so they don't linger on disk.
+    fn other_format_bucket_path(&self, short_key: &K) -> PathBuf
+    where
+        K: Display,
+    {
+        self.bucket_path_in_format(short_key, self.binary.is_none())
+    }
+
+    fn bucket_path_in_format(&self, short_key: &K, binary: bool) -> PathBuf
+    where
+        K: Display,
+    {
+        self.prefix
+            .join(short_key.to_string())
+            .with_extension(if binary { "bin" } else { "jsonl" })
+    }
+
+    /// The binary codec of this map, if it has one.
+    fn binary(&self) -> Option<&dyn BinaryCodec<L, V>> {
+        self.binary.as_deref()
+    }
+
     pub fn is_fully_loaded(&self) -> bool {
         self.fully_loaded.load(Ordering::SeqCst)
     }
 
     pub fn mark_loaded(&self) {
         self.fully_loaded.store(true, Ordering::SeqCst);
+        // Every key is now in memory, so we no longer need to remember which keys were removed.
+        self.removed.write().unwrap().clear();
     }
 
     pub fn total_short_keys(&self) -> usize {
@@ -181,6 +278,63 @@ impl<K, L, V> HierarchicalMap<K, L, V> {
         }
     }
 
+    /// Removes the given key from this hierarchical map, returning its value if it was in memory.
+    /// This doesn't change anything on disk, so if the map isn't fully loaded,
+    /// we remember the key so that [`HierarchicalMap::with`] and [`HierarchicalMap::contains_key`]
+    /// don't find it there until its bucket is serialised again.
+    pub fn remove(&self, key: &L) -> Option<V>
+    where
+        K: Ord,
+        L: Ord + Clone,
+    {
+        if !self.is_fully_loaded() {
+            self.removed.write().unwrap().insert(key.clone());
+        }
+        let short_key = (self.shorten)(key);
+        // We only need to read the outer map, since removing a key never adds a short key.
+        let guard = self.map.read().unwrap();
+        let mut inner_map = guard.get(&short_key)?.write().unwrap();
+        inner_map.remove(key)
+    }
+
+    /// Returns true if the given key is in this map.
+    /// If the key is not in memory, we check the cache on disk, without deserialising or storing its value.
+    /// Fails if the bucket on disk exists but can't be read.
+    pub fn contains_key(&self, key: &L) -> anyhow::Result<bool>
+    where
+        K: Ord + Display,
+        L: Ord + for<'a> Deserialize<'a>,
+    {
+        let short_key = (self.shorten)(key);
+        if let Some(inner_map) = self.map.read().unwrap().get(&short_key) {
+            if inner_map.read().unwrap().contains_key(key) {
+                return Ok(true);
+            }
+        }
+        if self.is_fully_loaded() || self.removed.read().unwrap().contains(key) {
+            return Ok(false);
+        }
+
+        let mut file = match self.storage.open(&self.bucket_path(&short_key)) {
+            Ok(file) => file,
+            Err(_) => return Ok(false),
+        };
+        match self.binary() {
+            Some(binary) => {
+                find_record_in_file(&mut file, key, binary).map(|entry| entry.is_some())
+            }
+            None => binary_search_line_in_file(
+                &mut file,
+                |line| {
+                    let (key, _): (L, IgnoredAny) = serde_json::from_str(line).unwrap();
+                    key
+                },
+                key,
+            )
+            .map(|line| line.is_some()),
+        }
+    }
+
     /// Obtains the value associated to the given key, applies `f` to it, and returns the result.
     /// If the key was not found, we check the cache on disk, and add the key-value pair to `self`.
     /// If the key was not found, and it cannot be found on disk, this returns [`None`].
@@ -199,20 +353,18 @@ impl<K, L, V> HierarchicalMap<K, L, V> {
             }
         }
         drop(outer_guard);
-        if self.is_fully_loaded() {
+        if self.is_fully_loaded() || self.removed.read().unwrap().contains(key) {
             return None;
         }
 
         // Try to load this key-value pair from disk.
-        let prefix = PathBuf::from("data").join(&self.prefix);
-        let mut file =
-            match std::fs::File::open(prefix.join(short_key.to_string()).with_extension("jsonl")) {
-                Ok(file) => file,
-                Err(_) => return None,
-            };
+        let mut file = match self.storage.open(&self.bucket_path(&short_key)) {
+            Ok(file) => file,
+            Err(_) => return None,
+        };
 
         // Now perform a binary search in the file to try to find the right key.
-        match find_entry_in_file(&mut file, key) {
+        match find_entry_in_file(&mut file, key, self.binary()) {
             Ok(Some(value)) => {
                 let result = f(&value);
                 self.insert(key.clone(), value);
@@ -251,6 +403,129 @@ impl<K, L, V> HierarchicalMap<K, L, V> {
         rx
     }
 
+    /// Like [`HierarchicalMap::with_all`], but doesn't require this map to be fully loaded.
+    /// If it isn't, we read each bucket from disk in turn, one key-value pair at a time,
+    /// so only a single entry is held in memory at once, rather than the whole map.
+    /// The short keys must already be known, for instance by calling [`HierarchicalMap::deserialize`] with `full` false.
+    /// If a bucket can't be read, the error is sent as the last message.
+    pub fn with_all_from_disk<T>(
+        &self,
+        message: String,
+        f: impl Fn(&L, &V) -> T + Send + 'static,
+    ) -> Receiver<anyhow::Result<T>>
+    where
+        K: Clone + Display + Send + Sync + 'static,
+        L: Ord + Send + Sync + for<'a> Deserialize<'a> + 'static,
+        V: Send + Sync + for<'a> Deserialize<'a> + 'static,
+        T: Send + Sync + 'static,
+    {
+        if self.is_fully_loaded() {
+            return self.with_all(message, move |key, value| Ok(f(key, value)));
+        }
+        let (tx, rx) = crossbeam::channel::bounded(1);
+        let this = self.clone();
+        std::thread::spawn(move || {
+            let short_keys = this.short_keys();
+            let progress_bar =
+                progress_bar::normal_progress_bar(short_keys.len() as u64).with_message(message);
+            for short_key in short_keys {
+                let result = this.for_each_in_bucket_file(&short_key, |key, value| {
+                    tx.send(Ok(f(&key, &value)))?;
+                    Ok(())
+                });
+                if let Err(err) = result {
+                    // If the receiver was dropped, nobody is listening for the error either.
+                    let _ = tx.send(Err(err));
+                    return;
+                }
+                progress_bar.inc(1);
+            }
+        });
+        rx
+    }
+
+    /// Like [`HierarchicalMap::with_all`], but applies `f` to the buckets of this map in parallel,
+    /// using one worker thread per available core.
+    /// The results are yielded in no particular order.
+    pub fn par_with_all<T>(
+        &self,
+        message: String,
+        f: impl Fn(&L, &V) -> T + Send + Sync + 'static,
+    ) -> Receiver<T>
+    where
+        K: Send + Sync + 'static,
+        L: Send + Sync + 'static,
+        V: Send + Sync + 'static,
+        T: Send + Sync + 'static,
+    {
+        assert!(self.is_fully_loaded());
+        let (tx, rx) = crossbeam::channel::bounded(16);
+        let progress_bar =
+            progress_bar::normal_progress_bar(self.total_keys() as u64).with_message(message);
+
+        let (bucket_tx, bucket_rx) = crossbeam::channel::unbounded();
+        for inner_map in self.map.read().unwrap().values() {
+            bucket_tx.send(Arc::clone(inner_map)).unwrap();
+        }
+        drop(bucket_tx);
+
+        let f = Arc::new(f);
+        let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+        for _ in 0..threads {
+            let tx = tx.clone();
+            let bucket_rx = bucket_rx.clone();
+            let progress_bar = progress_bar.clone();
+            let f = Arc::clone(&f);
+            std::thread::spawn::<_, anyhow::Result<()>>(move || {
+                while let Ok(inner_map) = bucket_rx.recv() {
+                    for (key, value) in inner_map.read().unwrap().iter() {
+                        tx.send(f(key, value))?;
+                        progress_bar.inc(1);
+                    }
+                }
+                Ok(())
+            });
+        }
+        rx
+    }
+
+    /// Merges every key-value pair of `other` into `self`.
+    /// If a key is present in both maps, `combine` is used to fold the value from `other` into the value in `self`.
+    ///
+    /// Both maps must use the same short key scheme; we check this by reshortening every key in `other`.
+    pub fn merge(&self, other: &Self, combine: impl Fn(&mut V, &V)) -> anyhow::Result<()>
+    where
+        K: Ord + Clone + Display,
+        L: Ord + Clone,
+        V: Clone,
+    {
+        for (short_key, other_inner_map) in other.map.read().unwrap().iter() {
+            let inner_map = Arc::clone(
+                self.map
+                    .write()
+                    .unwrap()
+                    .entry(short_key.clone())
+                    .or_default(),
+            );
+            let mut inner_map = inner_map.write().unwrap();
+            for (key, value) in other_inner_map.read().unwrap().iter() {
+                let expected_short_key = (self.shorten)(key);
+                if expected_short_key != *short_key {
+                    return Err(anyhow::Error::msg(format!(
+                        "short key schemes differ: found short key {short_key}, expected {expected_short_key}"
+                    )));
+                }
+                match inner_map.get_mut(key) {
+                    Some(existing) => combine(existing, value),
+                    None => {
+                        inner_map.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Returns the underying map.
     pub fn get_map(&self) -> &LockedBTreeMap<K, LockedBTreeMap<L, V>> {
         &self.map
@@ -258,7 +533,8 @@ impl<K, L, V> HierarchicalMap<K, L, V> {
 
     /// Serialises this hierarchical map using `self.prefix`, which should be something like `folder/information`.
     /// The output will be a file of the form `folder/information.json`, and a folder `folder/information/` which
-    /// will contain a `jsonl` file for each short key used.
+    /// will contain a `jsonl` file, or a `bin` file in the binary format, for each short key used.
+    /// Any bucket left over from serialising the map in the other format is removed.
     pub fn serialize(&self) -> anyhow::Result<()>
     where
         K: Send + Sync + Serialize + Display,
@@ -269,35 +545,25 @@ impl<K, L, V> HierarchicalMap<K, L, V> {
             panic!("hierarchical map not fully loaded before serialising");
         }
 
-        let prefix = PathBuf::from("data").join(&self.prefix);
-        std::fs::create_dir_all(&prefix)?;
-        let map = self.map.read().unwrap();
-
         // First, serialise the main map data.
-        {
-            let file = std::fs::File::create(prefix.with_extension("json"))?;
-            let mut writer = BufWriter::new(file);
-            serde_json::to_writer(&mut writer, &map.keys().collect::<Vec<_>>())?;
-            writer.flush()?;
-        }
+        self.serialize_short_keys()?;
 
         // Then, serialise all of the inner maps.
+        let map = self.map.read().unwrap();
         let threads = map
             .iter()
             .map(|(short_key, inner_map)| {
-                let prefix = prefix.to_owned();
-                let short_key = short_key.to_string();
+                let path = self.bucket_path(short_key);
+                let other_path = self.other_format_bucket_path(short_key);
+                let storage = Arc::clone(&self.storage);
                 let inner_map = Arc::clone(inner_map);
+                let binary = self.binary.clone();
                 std::thread::spawn::<_, anyhow::Result<()>>(move || {
-                    let file =
-                        std::fs::File::create(prefix.join(short_key).with_extension("jsonl"))?;
-                    let mut writer = BufWriter::new(file);
-                    for (key, value) in inner_map.read().unwrap().iter() {
-                        serde_json::to_writer(&mut writer, &(key, value))?;
-                        writeln!(writer)?;
-                    }
-                    writer.flush()?;
-                    Ok(())
+                    let inner_map = inner_map.read().unwrap();
+                    storage.write(&path, &mut |writer| {
+                        write_bucket(writer, &inner_map, binary.as_deref())
+                    })?;
+                    Ok(storage.remove(&other_path)?)
                 })
             })
             .collect::<Vec<_>>();
@@ -309,25 +575,111 @@ impl<K, L, V> HierarchicalMap<K, L, V> {
         Ok(())
     }
 
+    /// Serialises the list of short keys in this map, without serialising any of the inner maps.
+    /// Together with [`HierarchicalMap::serialize_bucket`], this can be used to serialise a map
+    /// that is built one bucket at a time.
+    pub fn serialize_short_keys(&self) -> anyhow::Result<()>
+    where
+        K: Serialize,
+    {
+        let short_keys = self.map.read().unwrap();
+        self.storage.write(&self.short_keys_path(), &mut |writer| {
+            Ok(serde_json::to_writer(
+                writer,
+                &short_keys.keys().collect::<Vec<_>>(),
+            )?)
+        })
+    }
+
+    /// Serialises the inner map associated to the given short key, then unloads it from memory.
+    /// The short key is kept, so entries in this bucket can still be found on disk with [`HierarchicalMap::with`].
+    pub fn serialize_bucket(&self, short_key: &K) -> anyhow::Result<()>
+    where
+        K: Ord + Display,
+        L: Ord + Serialize,
+        V: Serialize,
+    {
+        let Some(inner_map) = self.map.read().unwrap().get(short_key).cloned() else {
+            return Ok(());
+        };
+        let mut inner_map = inner_map.write().unwrap();
+        self.storage
+            .write(&self.bucket_path(short_key), &mut |writer| {
+                write_bucket(writer, &inner_map, self.binary())
+            })?;
+        self.storage
+            .remove(&self.other_format_bucket_path(short_key))?;
+        inner_map.clear();
+        // The bucket on disk no longer contains any keys that were removed from it.
+        self.removed
+            .write()
+            .unwrap()
+            .retain(|key| (self.shorten)(key) != *short_key);
+        Ok(())
+    }
+
+    /// Returns the short keys of this map.
+    pub fn short_keys(&self) -> Vec<K>
+    where
+        K: Clone,
+    {
+        self.map.read().unwrap().keys().cloned().collect()
+    }
+
+    /// Calls `f` on every key-value pair serialised for the given short key,
+    /// without storing them in this map.
+    /// Keys that were removed from this map since the bucket was serialised are skipped.
+    pub fn for_each_in_bucket_file(
+        &self,
+        short_key: &K,
+        mut f: impl FnMut(L, V) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()>
+    where
+        K: Display,
+        L: Ord + for<'a> Deserialize<'a>,
+        V: for<'a> Deserialize<'a>,
+    {
+        read_bucket(
+            self.storage.open(&self.bucket_path(short_key))?,
+            self.binary(),
+            |key, value| {
+                // We don't hold the lock while calling `f`, in case it removes keys itself.
+                if self.removed.read().unwrap().contains(&key) {
+                    Ok(())
+                } else {
+                    f(key, value)
+                }
+            },
+        )
+    }
+
     /// If `full` is false, we'll only deserialise the outermost map, and ignore the inner maps.
     /// If successful, this function returns `Ok(true)`.
-    /// If no data has been serialised, this function returns `Ok(false)`.
+    /// If no data has been serialised, or some of its buckets are missing, for instance because the `prune` command
+    /// deleted them, this function returns `Ok(false)`, so that the caller regenerates the map.
     pub fn deserialize(&self, full: bool) -> anyhow::Result<bool>
     where
         K: for<'a> Deserialize<'a> + Ord + Display,
         L: Send + Sync + for<'a> Deserialize<'a> + Ord + 'static,
         V: Send + Sync + for<'a> Deserialize<'a> + 'static,
     {
-        let prefix = PathBuf::from("data").join(&self.prefix);
         let mut map = self.map.write().unwrap();
 
         {
             // First, deserialise the main map data.
-            let file = match std::fs::File::open(prefix.with_extension("json")) {
+            let file = match self.storage.open(&self.short_keys_path()) {
                 Ok(file) => file,
                 Err(_) => return Ok(false),
             };
             let keys: Vec<K> = serde_json::from_reader(BufReader::new(file))?;
+            // If a bucket is missing, perhaps because it was pruned or written in a different format,
+            // we regenerate the map.
+            if keys
+                .iter()
+                .any(|short_key| !self.storage.is_file(&self.bucket_path(short_key)))
+            {
+                return Ok(false);
+            }
             for short_key in keys {
                 map.insert(short_key, Default::default());
             }
@@ -337,23 +689,43 @@ impl<K, L, V> HierarchicalMap<K, L, V> {
             return Ok(true);
         }
 
+        let start_time = Instant::now();
+
         // Then, deserialise all of the inner maps.
-        let threads = map
-            .iter()
-            .map(|(short_key, inner_map)| {
-                let prefix = prefix.to_owned();
-                let short_key = short_key.to_string();
-                let inner_map = Arc::clone(inner_map);
+        // There may be hundreds of buckets, so to avoid thrashing the disk,
+        // we read them using one worker thread per available core.
+        let progress_bar = self.load_message.as_ref().map(|message| {
+            progress_bar::normal_progress_bar(map.len() as u64).with_message(message.clone())
+        });
+        let (bucket_tx, bucket_rx) = crossbeam::channel::unbounded();
+        for (short_key, inner_map) in map.iter() {
+            bucket_tx.send((self.bucket_path(short_key), Arc::clone(inner_map)))?;
+        }
+        drop(bucket_tx);
+
+        let threads = std::thread::available_parallelism()
+            .map_or(1, |threads| threads.get())
+            .min(map.len());
+        let threads = (0..threads)
+            .map(|_| {
+                let bucket_rx = bucket_rx.clone();
+                let storage = Arc::clone(&self.storage);
+                let binary = self.binary.clone();
+                let removed = Arc::clone(&self.removed);
+                let progress_bar = progress_bar.clone();
                 std::thread::spawn::<_, anyhow::Result<()>>(move || {
-                    let mut inner_map = inner_map.write().unwrap();
-                    let file = std::fs::File::open(prefix.join(short_key).with_extension("jsonl"))?;
-                    for line in BufReader::new(file).lines() {
-                        let line = line?;
-                        if line.is_empty() {
-                            continue;
+                    while let Ok((path, inner_map)) = bucket_rx.recv() {
+                        let mut inner_map = inner_map.write().unwrap();
+                        let removed = removed.read().unwrap();
+                        read_bucket(storage.open(&path)?, binary.as_deref(), |key, value| {
+                            if !removed.contains(&key) {
+                                inner_map.insert(key, value);
+                            }
+                            Ok(())
+                        })?;
+                        if let Some(progress_bar) = &progress_bar {
+                            progress_bar.inc(1);
                         }
-                        let (key, value) = serde_json::from_str(&line)?;
-                        inner_map.insert(key, value);
                     }
                     Ok(())
                 })
@@ -363,19 +735,174 @@ impl<K, L, V> HierarchicalMap<K, L, V> {
         for thread in threads {
             thread.join().map_err(|_| anyhow::Error::msg("panic"))??;
         }
+        if let Some(progress_bar) = progress_bar {
+            progress_bar.finish();
+        }
 
         self.mark_loaded();
 
+        if LOAD_STATS.load(Ordering::SeqCst) {
+            // The size of the serialised buckets is a rough proxy for the memory used by the loaded map.
+            let mut size = 0;
+            for short_key in map.keys() {
+                size += self.storage.len(&self.bucket_path(short_key)).unwrap_or(0);
+            }
+            println!(
+                "Loaded {} in {} ({} on disk)",
+                self.prefix.display(),
+                HumanDuration(start_time.elapsed()),
+                HumanBytes(size)
+            );
+        }
+
+        Ok(true)
+    }
+
+    /// Deserialises the single inner map associated to the given short key, without loading any other inner maps.
+    /// If successful, this function returns `Ok(true)`.
+    /// If no data has been serialised for this short key, this function returns `Ok(false)`.
+    pub fn deserialize_bucket(&self, short_key: K) -> anyhow::Result<bool>
+    where
+        K: Ord + Display,
+        L: for<'a> Deserialize<'a> + Ord,
+        V: for<'a> Deserialize<'a>,
+    {
+        let path = self.bucket_path(&short_key);
+        if !self.storage.is_file(&path) {
+            return Ok(false);
+        }
+
+        let inner_map = Arc::clone(self.map.write().unwrap().entry(short_key).or_default());
+        let mut inner_map = inner_map.write().unwrap();
+        let removed = self.removed.read().unwrap();
+        read_bucket(self.storage.open(&path)?, self.binary(), |key, value| {
+            if !removed.contains(&key) {
+                inner_map.insert(key, value);
+            }
+            Ok(())
+        })?;
         Ok(true)
     }
 }
 
+/// Encodes the key-value pairs of a bucket as binary records.
+///
+/// A binary bucket file starts with the number of records `n` and then the byte offset of each record,
+/// relative to the end of this table, each as a little-endian `u64`.
+/// The records follow, sorted by key, so that we can binary search for a key by seeking to the records in turn.
+pub trait BinaryCodec<L, V>: Send + Sync {
+    fn write_record(&self, writer: &mut dyn Write, key: &L, value: &V) -> anyhow::Result<()>;
+    fn read_record(&self, reader: &mut dyn Read) -> anyhow::Result<(L, V)>;
+}
+
+/// Encodes each record as its key followed by its value, using their [`BytesSerde`] implementations.
+struct BytesSerdeCodec;
+
+impl<L, V> BinaryCodec<L, V> for BytesSerdeCodec
+where
+    L: BytesSerde,
+    V: BytesSerde,
+{
+    fn write_record(&self, mut writer: &mut dyn Write, key: &L, value: &V) -> anyhow::Result<()> {
+        BytesSerde::serialize(key, &mut writer)?;
+        BytesSerde::serialize(value, &mut writer)
+    }
+
+    fn read_record(&self, mut reader: &mut dyn Read) -> anyhow::Result<(L, V)> {
+        Ok((
+            <L as BytesSerde>::deserialize(&mut reader)?,
+            <V as BytesSerde>::deserialize(&mut reader)?,
+        ))
+    }
+}
+
+fn read_u64(reader: &mut impl Read) -> anyhow::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Writes every key-value pair of an inner map to the given file,
+/// either one per line as JSON, or as binary records if a codec is given.
+fn write_bucket<L, V>(
+    mut writer: impl Write,
+    inner_map: &BTreeMap<L, V>,
+    binary: Option<&dyn BinaryCodec<L, V>>,
+) -> anyhow::Result<()>
+where
+    L: Serialize,
+    V: Serialize,
+{
+    if let Some(binary) = binary {
+        // We need the offset of every record before writing any of them.
+        let mut offsets = Vec::with_capacity(inner_map.len());
+        let mut records = Vec::new();
+        for (key, value) in inner_map.iter() {
+            offsets.push(records.len() as u64);
+            binary.write_record(&mut records, key, value)?;
+        }
+        writer.write_all(&(offsets.len() as u64).to_le_bytes())?;
+        for offset in offsets {
+            writer.write_all(&offset.to_le_bytes())?;
+        }
+        writer.write_all(&records)?;
+        return Ok(());
+    }
+
+    for (key, value) in inner_map.iter() {
+        serde_json::to_writer(&mut writer, &(key, value))?;
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+/// Reads every key-value pair in the given inner map file, written by [`write_bucket`] with the same codec.
+fn read_bucket<L, V>(
+    file: impl Read,
+    binary: Option<&dyn BinaryCodec<L, V>>,
+    mut f: impl FnMut(L, V) -> anyhow::Result<()>,
+) -> anyhow::Result<()>
+where
+    L: for<'a> Deserialize<'a>,
+    V: for<'a> Deserialize<'a>,
+{
+    if let Some(binary) = binary {
+        let mut reader = BufReader::new(file);
+        let records = read_u64(&mut reader)?;
+        // Skip the table of offsets, since we read the records in order.
+        std::io::copy(&mut (&mut reader).take(records * 8), &mut std::io::sink())?;
+        for _ in 0..records {
+            let (key, value) = binary.read_record(&mut reader)?;
+            f(key, value)?;
+        }
+        return Ok(());
+    }
+
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let (key, value) = serde_json::from_str(&line)?;
+        f(key, value)?;
+    }
+    Ok(())
+}
+
 /// Performs a binary search on the given file to try to find the given key-value pair.
-fn find_entry_in_file<L, V>(file: &mut File, key: &L) -> anyhow::Result<Option<V>>
+fn find_entry_in_file<L, V>(
+    file: &mut impl ReadSeek,
+    key: &L,
+    binary: Option<&dyn BinaryCodec<L, V>>,
+) -> anyhow::Result<Option<V>>
 where
     L: Ord + for<'a> Deserialize<'a>,
     V: for<'a> Deserialize<'a>,
 {
+    if let Some(binary) = binary {
+        return find_record_in_file(file, key, binary);
+    }
+
     binary_search_line_in_file(
         file,
         |line| {
@@ -391,3 +918,297 @@ where
         })
     })
 }
+
+/// Performs a binary search on the records of the given binary bucket file to try to find the given key.
+fn find_record_in_file<L, V>(
+    file: &mut impl ReadSeek,
+    key: &L,
+    binary: &dyn BinaryCodec<L, V>,
+) -> anyhow::Result<Option<V>>
+where
+    L: Ord,
+{
+    file.seek(SeekFrom::Start(0))?;
+    let records = read_u64(file)?;
+    let records_start = 8 * (records + 1);
+    let (mut low, mut high) = (0, records);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        file.seek(SeekFrom::Start(8 * (middle + 1)))?;
+        let offset = read_u64(file)?;
+        file.seek(SeekFrom::Start(records_start + offset))?;
+        let (middle_key, value) = binary.read_record(&mut BufReader::new(&mut *file))?;
+        match middle_key.cmp(key) {
+            std::cmp::Ordering::Less => low = middle + 1,
+            std::cmp::Ordering::Greater => high = middle,
+            std::cmp::Ordering::Equal => return Ok(Some(value)),
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_storage::MemoryStorage;
+
+    type TestMap = HierarchicalMap<u8, u32, Vec<u32>>;
+
+    /// A map stored in the given storage, with four buckets, optionally in the binary format.
+    fn test_map(storage: &MemoryStorage, binary: bool) -> TestMap {
+        let map = HierarchicalMap::new(PathBuf::from("links"), |id: &u32| (id % 4) as u8)
+            .with_storage(Arc::new(storage.clone()));
+        if binary {
+            map.with_binary_format()
+        } else {
+            map
+        }
+    }
+
+    fn links(id: u32) -> Vec<u32> {
+        (0..id % 5).map(|i| id * 10 + i).collect()
+    }
+
+    /// Serialises a map containing the IDs `0..20` to a new storage.
+    fn serialized_storage(binary: bool) -> MemoryStorage {
+        let storage = MemoryStorage::default();
+        let map = test_map(&storage, binary);
+        for id in 0..20 {
+            map.insert(id, links(id));
+        }
+        map.mark_loaded();
+        map.serialize().unwrap();
+        storage
+    }
+
+    #[test]
+    fn serialize_then_deserialize_fully() {
+        for binary in [false, true] {
+            let storage = serialized_storage(binary);
+            let map = test_map(&storage, binary);
+            assert!(map.deserialize(true).unwrap());
+            assert!(map.is_fully_loaded());
+            assert_eq!(map.total_short_keys(), 4);
+            assert_eq!(map.total_keys(), 20);
+            for id in 0..20 {
+                assert_eq!(map.with(&id, |links| links.clone()), Some(links(id)));
+            }
+            // A fully loaded map never looks for missing keys in storage.
+            assert_eq!(map.with(&20, |links| links.clone()), None);
+        }
+    }
+
+    #[test]
+    fn lazy_lookups_fall_back_to_storage() {
+        for binary in [false, true] {
+            let storage = serialized_storage(binary);
+            let map = test_map(&storage, binary);
+            assert!(map.deserialize(false).unwrap());
+            assert!(!map.is_fully_loaded());
+            assert_eq!(map.total_short_keys(), 4);
+            assert_eq!(map.total_keys(), 0);
+
+            assert_eq!(map.with(&7, |links| links.clone()), Some(links(7)));
+            assert_eq!(map.with(&0, |links| links.clone()), Some(links(0)));
+            assert_eq!(map.with(&19, |links| links.clone()), Some(links(19)));
+            assert_eq!(map.with(&100, |links| links.clone()), None);
+            // Values found in storage are kept in memory.
+            assert_eq!(map.total_keys(), 3);
+        }
+    }
+
+    #[test]
+    fn deserialize_without_data_fails() {
+        for binary in [false, true] {
+            let map = test_map(&MemoryStorage::default(), binary);
+            assert!(!map.deserialize(true).unwrap());
+            assert!(!map.deserialize(false).unwrap());
+        }
+    }
+
+    #[test]
+    fn deserialize_in_another_format_fails() {
+        let storage = serialized_storage(false);
+        assert!(!test_map(&storage, true).deserialize(false).unwrap());
+    }
+
+    #[test]
+    fn serialize_bucket_unloads_it() {
+        for binary in [false, true] {
+            let storage = MemoryStorage::default();
+            let map = test_map(&storage, binary);
+            for id in 0..20 {
+                map.insert(id, links(id));
+            }
+            map.serialize_short_keys().unwrap();
+            for short_key in map.short_keys() {
+                map.serialize_bucket(&short_key).unwrap();
+            }
+            assert_eq!(map.total_short_keys(), 4);
+            assert_eq!(map.total_keys(), 0);
+            assert_eq!(map.with(&13, |links| links.clone()), Some(links(13)));
+
+            let map = test_map(&storage, binary);
+            assert!(map.deserialize(true).unwrap());
+            assert_eq!(map.total_keys(), 20);
+        }
+    }
+
+    #[test]
+    fn with_all_from_disk_streams_every_entry() {
+        for binary in [false, true] {
+            let storage = serialized_storage(binary);
+            let map = test_map(&storage, binary);
+            assert!(map.deserialize(false).unwrap());
+            let mut ids = map
+                .with_all_from_disk(String::new(), |id, links| (*id, links.clone()))
+                .iter()
+                .collect::<anyhow::Result<Vec<_>>>()
+                .unwrap();
+            ids.sort();
+            assert_eq!(ids, (0..20).map(|id| (id, links(id))).collect::<Vec<_>>());
+            assert_eq!(map.total_keys(), 0);
+        }
+    }
+
+    #[test]
+    fn with_all_from_disk_reports_missing_buckets() {
+        for binary in [false, true] {
+            let storage = serialized_storage(binary);
+            let map = test_map(&storage, binary);
+            assert!(map.deserialize(false).unwrap());
+            storage.remove(&map.bucket_path(&2)).unwrap();
+            let results = map
+                .with_all_from_disk(String::new(), |id, _| *id)
+                .iter()
+                .collect::<Vec<_>>();
+            // The buckets are read in order, so the error comes after the entries of the first two buckets.
+            assert_eq!(results.len(), 11);
+            assert!(results[..10].iter().all(|result| result.is_ok()));
+            assert!(results[10].is_err());
+        }
+    }
+
+    #[test]
+    fn removed_keys_are_not_found_on_disk() {
+        for binary in [false, true] {
+            let storage = serialized_storage(binary);
+            let map = test_map(&storage, binary);
+            assert!(map.deserialize(false).unwrap());
+
+            // Key 6 was loaded into memory before being removed, but key 10 only exists on disk.
+            assert_eq!(map.with(&6, |links| links.clone()), Some(links(6)));
+            assert_eq!(map.remove(&6), Some(links(6)));
+            assert_eq!(map.remove(&10), None);
+            for id in [6, 10] {
+                assert!(!map.contains_key(&id).unwrap());
+                assert_eq!(map.with(&id, |links| links.clone()), None);
+            }
+            assert!(map.contains_key(&14).unwrap());
+            let ids = map
+                .with_all_from_disk(String::new(), |id, _| *id)
+                .iter()
+                .collect::<anyhow::Result<Vec<_>>>()
+                .unwrap();
+            assert_eq!(ids.len(), 18);
+
+            // Once the bucket is rewritten without the removed keys, they can be inserted again.
+            map.deserialize_bucket(2).unwrap();
+            assert!(!map.contains_key(&10).unwrap());
+            map.serialize_bucket(&2).unwrap();
+            map.deserialize_bucket(2).unwrap();
+            map.insert(10, vec![1]);
+            map.serialize_bucket(&2).unwrap();
+            assert_eq!(map.with(&10, |links| links.clone()), Some(vec![1]));
+            assert_eq!(map.with(&6, |links| links.clone()), None);
+            assert_eq!(map.with(&14, |links| links.clone()), Some(links(14)));
+        }
+    }
+
+    #[test]
+    fn binary_buckets_round_trip() {
+        let inner_map = [0, 1, 7, 255, 256, 70_000, u32::MAX]
+            .into_iter()
+            .map(|id| (id, links(id)))
+            .collect::<BTreeMap<_, _>>();
+        let mut bytes = Vec::new();
+        write_bucket(&mut bytes, &inner_map, Some(&BytesSerdeCodec)).unwrap();
+        // The record count and offsets come first, then the key, length and links of each record.
+        let links = inner_map.values().map(Vec::len).sum::<usize>();
+        assert_eq!(
+            bytes.len(),
+            8 * (inner_map.len() + 1) + 4 * links + 8 * inner_map.len()
+        );
+
+        let mut read = BTreeMap::new();
+        read_bucket(
+            &bytes[..],
+            Some(&BytesSerdeCodec),
+            |id: u32, links: Vec<u32>| {
+                read.insert(id, links);
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(read, inner_map);
+    }
+
+    #[test]
+    fn binary_search_finds_records() {
+        let ids = [2, 3, 5, 8, 13, 21, 34];
+        let inner_map = ids
+            .into_iter()
+            .map(|id| (id, links(id)))
+            .collect::<BTreeMap<_, _>>();
+        let mut bytes = Vec::new();
+        write_bucket(&mut bytes, &inner_map, Some(&BytesSerdeCodec)).unwrap();
+        let mut file = std::io::Cursor::new(bytes);
+        for id in 0..40 {
+            let expected = ids.contains(&id).then(|| links(id));
+            assert_eq!(
+                find_record_in_file(&mut file, &id, &BytesSerdeCodec).unwrap(),
+                expected,
+                "{id}"
+            );
+        }
+
+        let mut bytes = Vec::new();
+        write_bucket(
+            &mut bytes,
+            &BTreeMap::<u32, Vec<u32>>::new(),
+            Some(&BytesSerdeCodec),
+        )
+        .unwrap();
+        let mut file = std::io::Cursor::new(bytes);
+        assert_eq!(
+            find_record_in_file::<u32, Vec<u32>>(&mut file, &0, &BytesSerdeCodec).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn regenerating_in_another_format_removes_old_buckets() {
+        for binary in [false, true] {
+            let storage = serialized_storage(binary);
+            let old_map = test_map(&storage, binary);
+            let map = test_map(&storage, !binary);
+            assert!(!map.deserialize(false).unwrap());
+            for id in 0..20 {
+                map.insert(id, links(id));
+            }
+            map.mark_loaded();
+            map.serialize().unwrap();
+            for short_key in 0..4 {
+                assert!(!storage.is_file(&old_map.bucket_path(&short_key)));
+                assert!(storage.is_file(&map.bucket_path(&short_key)));
+            }
+
+            // Serialising a single bucket removes its counterpart too.
+            let map = test_map(&storage, binary);
+            map.insert(1, links(1));
+            map.serialize_bucket(&1).unwrap();
+            assert!(storage.is_file(&map.bucket_path(&1)));
+            assert!(!storage.is_file(&map.other_format_bucket_path(&1)));
+        }
+    }
+}
@@ -0,0 +1,16 @@
+//! Finds paths between Wikipedia articles, and computes statistics about the link graph, from the database dumps.
+//! The command line interface lives in `main.rs`; everything it uses is exposed here,
+//! so that the title map, link graph and page parsing can be used from other crates.
+
+pub mod atomic_write;
+pub mod binary_search_line;
+pub mod commands;
+pub mod data_dir;
+pub mod hierarchical_map;
+pub mod map_storage;
+pub mod memoise;
+pub mod page;
+pub mod parse;
+pub mod progress_bar;
+pub mod stats;
+pub mod titles;
@@ -1,18 +1,38 @@
-pub mod binary_search_line;
-pub mod commands;
-pub mod hierarchical_map;
-pub mod memoise;
-pub mod page;
-pub mod parse;
-pub mod progress_bar;
-pub mod titles;
+use std::path::PathBuf;
 
 use clap::{Parser, Subcommand};
+use wikipedia::{commands, data_dir, hierarchical_map, page, progress_bar, titles};
 
 #[derive(Debug, Parser)]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// Writes progress as newline-delimited JSON events to this file descriptor
+    #[arg(long, global = true, value_name = "FD")]
+    progress_json: Option<i32>,
+    /// Hides the progress bars on the terminal
+    #[arg(long, global = true)]
+    hide_progress_bars: bool,
+    /// Reports how long each fully loaded cache took to load, and its size on disk
+    #[arg(long, global = true)]
+    load_stats: bool,
+    /// The directory where downloaded dumps and cached data are stored
+    #[arg(
+        long,
+        global = true,
+        env = "WIKIPEDIA_DATA_DIR",
+        default_value = "data"
+    )]
+    data_dir: PathBuf,
+    /// Restricts the link graph to the articles listed in this file, given as one ID or title per line
+    #[arg(long, global = true, value_name = "FILE")]
+    allowlist: Option<PathBuf>,
+    /// Makes links to redirects in the link graph point to the pages they redirect to
+    #[arg(long, global = true)]
+    resolve_redirects: bool,
+    /// Panics on unrecognised elements of a page in the dump, instead of warning about them and skipping them
+    #[arg(long, global = true, hide = true)]
+    strict_parsing: bool,
 }
 
 #[derive(Debug, Subcommand)]
@@ -21,25 +41,316 @@ enum Commands {
     Download {
         #[arg(short, long)]
         date: Option<String>,
+        /// Also downloads the SQL dump of the redirect table, which speeds up computing redirects
+        #[arg(long)]
+        redirect_table: bool,
+        /// Pins the data directory to this dump, so that later downloads resume it instead of switching dumps
+        #[arg(long)]
+        pin: bool,
+        /// Allows switching away from a pinned dump
+        #[arg(long)]
+        force: bool,
+        /// The number of files to download at once
+        #[arg(short, long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+        jobs: u32,
+        /// The number of times to retry a request that fails because of a connection or server error,
+        /// and to resume a file whose connection drops partway through
+        #[arg(long, default_value_t = commands::download::DEFAULT_RETRIES)]
+        retries: u32,
+        /// Starts downloading even if there does not seem to be enough free disk space
+        #[arg(long)]
+        skip_space_check: bool,
     },
     /// Displays a random article
     Random {},
     /// Displays the list of articles linked from an article
-    Links { article: String },
+    Links {
+        #[arg(value_parser = titles::parse_article_title)]
+        article: String,
+        /// Falls back to a case-insensitive match if the title is not found
+        #[arg(long)]
+        ignore_case: bool,
+    },
     /// Finds the shortest path between the two articles
-    Path { start: String, end: String },
+    Path {
+        #[arg(value_parser = titles::parse_article_title)]
+        start: String,
+        #[arg(value_parser = titles::parse_article_title)]
+        end: String,
+        /// Falls back to a case-insensitive match if a title is not found
+        #[arg(long)]
+        ignore_case: bool,
+        /// Reports the number of articles found at each depth of the search
+        #[arg(long)]
+        levels: bool,
+        /// Chooses whether to search in one or both directions
+        #[arg(long, value_enum, default_value_t)]
+        strategy: commands::shortest_path::SearchStrategy,
+        #[command(flatten)]
+        exclusions: commands::shortest_path::Exclusions,
+        /// Doesn't count following a link to a redirect page towards the degree of the path
+        #[arg(long, conflicts_with_all = ["levels", "strategy"])]
+        redirects_free: bool,
+        /// Also reports how many distinct minimal paths there are between the two articles
+        #[arg(long, conflicts_with = "redirects_free")]
+        count: bool,
+    },
     /// Finds some long shortest paths between two articles
-    LongPaths {},
+    LongPaths {
+        /// Only prints paths of at least this degree
+        #[arg(long, default_value_t = 0)]
+        min_length: usize,
+    },
+    /// Merges two link maps (given as paths relative to the data directory) into a new map
+    MergeMaps {
+        first: PathBuf,
+        second: PathBuf,
+        output: PathBuf,
+    },
+    /// Summarises the redirects in the dump
+    Redirects {
+        /// Lists all redirect cycles and dangling redirects
+        #[arg(long)]
+        detect_cycles: bool,
+    },
+    /// Exports the link graph for use in other tools
+    Export {
+        #[arg(short, long, value_enum)]
+        format: commands::export::ExportFormat,
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Computes the fraction of links that are reciprocated
+    Reciprocity {
+        /// The number of links to sample
+        #[arg(short, long, default_value_t = 10_000)]
+        samples: u64,
+        /// Checks every link instead of sampling
+        #[arg(long)]
+        exact: bool,
+        /// Lists this many articles with the most reciprocal links (requires `--exact`)
+        #[arg(long, requires = "exact")]
+        top: Option<usize>,
+    },
+    /// Prints the contents of a single bucket of a cached map, such as `outgoing_links` bucket `42`
+    #[command(hide = true)]
+    Bucket { map: String, short_key: String },
+    /// Finds the shortest paths between each `start<TAB>end` pair in the input file,
+    /// writing the results as JSON lines to the output file
+    BatchPath { input: PathBuf, output: PathBuf },
+    /// Displays the approximate number of words in an article
+    WordCount {
+        #[arg(value_parser = titles::parse_article_title)]
+        article: String,
+    },
+    /// Writes the breadth-first search tree from an article as a list of parent-child edges
+    Tree {
+        #[arg(value_parser = titles::parse_article_title)]
+        article: String,
+        /// The maximum depth of the tree
+        #[arg(short, long, default_value_t = 2)]
+        depth: u32,
+        /// Stops the search after discovering this many articles
+        #[arg(long, default_value_t = 100_000)]
+        max_nodes: usize,
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Summarises how the number of links from an article relates to its length
+    Density {},
+    /// Lists the articles that link to an article without being linked back
+    OneWay {
+        #[arg(value_parser = titles::parse_article_title)]
+        article: String,
+        /// Also lists the articles it links to that don't link back
+        #[arg(long)]
+        reverse: bool,
+    },
+    /// Deletes the least recently used cache buckets until the caches fit in the given number of bytes;
+    /// a map with any deleted buckets is regenerated in full, rewriting all of its buckets, when next needed
+    Prune { max_size: u64 },
+    /// Finds a minimal path of the given degree between a random pair of articles
+    RandomPath {
+        degree: usize,
+        /// The number of pairs of articles to try before giving up
+        #[arg(long, default_value_t = 100_000)]
+        max_attempts: u64,
+    },
+    /// Lists the pages in the compressed block at the given byte offset of an articles file
+    Block {
+        offset: u64,
+        /// The name of the articles file, as listed in the dump status; defaults to the first articles file
+        #[arg(long)]
+        file: Option<String>,
+    },
+    /// Measures how quickly pages are parsed and their links found
+    #[command(hide = true)]
+    Bench {
+        /// The number of pages to parse
+        #[arg(default_value_t = 100_000)]
+        articles: u64,
+        /// Only parses pages in the main namespace, skipping blocks of the dump that contain none
+        #[arg(long)]
+        main_namespace_only: bool,
+    },
+    /// Lists the available versions of the dump, and whether each has finished
+    ListDumps {},
+    /// Lists the articles that appear most often in the middle of shortest paths between random pairs of articles
+    Hubs {
+        /// The number of random pairs of articles to sample
+        #[arg(short, long, default_value_t = 1_000)]
+        samples: u64,
+        /// The number of articles to list
+        #[arg(long, default_value_t = 20)]
+        top: usize,
+    },
+    /// Checks the downloaded files of the current dump against their published MD5 hashes
+    Verify {},
+    /// Prints only the degree of separation between two articles,
+    /// exiting with a non-zero status if no path exists
+    Distance {
+        #[arg(value_parser = titles::parse_article_title)]
+        start: String,
+        #[arg(value_parser = titles::parse_article_title)]
+        end: String,
+    },
+    /// Displays the list of articles linked from the "See also" section of an article
+    SeeAlso {
+        #[arg(value_parser = titles::parse_article_title)]
+        article: String,
+        /// Falls back to a case-insensitive match if the title is not found
+        #[arg(long)]
+        ignore_case: bool,
+    },
+    /// Checks that the ID to title and title to ID maps agree with each other
+    CheckTitles {
+        /// Rebuilds the title to ID map from the ID to title map if they disagree
+        #[arg(long)]
+        repair: bool,
+    },
+    /// Summarises the link graph: its size, how the degrees of articles are distributed,
+    /// and the articles with the most links
+    Stats {
+        /// The number of articles with the most links to list in each direction
+        #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u64).range(0..=commands::stats::MAX_TOP as u64))]
+        top: u64,
+    },
 }
 
 fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
+    data_dir::set_data_dir(cli.data_dir.clone())?;
+    if let Some(fd) = cli.progress_json {
+        progress_bar::enable_json_progress(fd)?;
+    }
+    if cli.hide_progress_bars {
+        progress_bar::hide_progress_bars();
+    }
+    if cli.load_stats {
+        hierarchical_map::enable_load_stats();
+    }
+    if cli.strict_parsing {
+        page::enable_strict_parsing();
+    }
+    if cli.resolve_redirects {
+        commands::links::resolve_redirects_in_links();
+    }
+    // These commands work on the downloaded files themselves, which may not include an articles dump yet.
+    if !matches!(
+        cli.command,
+        Commands::Download { .. } | Commands::ListDumps {} | Commands::Verify {}
+    ) {
+        titles::load_namespaces()?;
+    }
+    if let Some(allowlist) = &cli.allowlist {
+        commands::links::restrict_to_allowlist(allowlist)?;
+    }
 
     match cli.command {
-        Commands::Download { date } => commands::download::execute(date),
+        Commands::Download {
+            date,
+            redirect_table,
+            pin,
+            force,
+            jobs,
+            retries,
+            skip_space_check,
+        } => commands::download::execute(
+            date,
+            redirect_table,
+            pin,
+            force,
+            jobs,
+            retries,
+            skip_space_check,
+        ),
         Commands::Random {} => commands::random_article::execute(),
-        Commands::Links { article } => commands::links::execute(article),
-        Commands::Path { start, end } => commands::shortest_path::execute(start, end),
-        Commands::LongPaths {} => commands::long_paths::execute(),
+        Commands::Links {
+            article,
+            ignore_case,
+        } => commands::links::execute(article, ignore_case),
+        Commands::Path {
+            start,
+            end,
+            ignore_case,
+            levels,
+            strategy,
+            exclusions,
+            redirects_free,
+            count,
+        } => commands::shortest_path::execute(
+            start,
+            end,
+            ignore_case,
+            levels,
+            strategy,
+            exclusions,
+            redirects_free,
+            count,
+        ),
+        Commands::LongPaths { min_length } => commands::long_paths::execute(min_length),
+        Commands::MergeMaps {
+            first,
+            second,
+            output,
+        } => commands::merge_maps::execute(first, second, output),
+        Commands::Redirects { detect_cycles } => commands::redirects::execute(detect_cycles),
+        Commands::Export { format, output } => commands::export::execute(format, &output),
+        Commands::Reciprocity {
+            samples,
+            exact,
+            top,
+        } => commands::reciprocity::execute(samples, exact, top),
+        Commands::Bucket { map, short_key } => commands::bucket::execute(map, short_key),
+        Commands::BatchPath { input, output } => commands::batch_path::execute(&input, &output),
+        Commands::WordCount { article } => commands::word_count::execute(article),
+        Commands::Tree {
+            article,
+            depth,
+            max_nodes,
+            output,
+        } => commands::tree::execute(article, depth, max_nodes, &output),
+        Commands::Density {} => commands::density::execute(),
+        Commands::OneWay { article, reverse } => commands::one_way::execute(article, reverse),
+        Commands::Prune { max_size } => commands::prune::execute(max_size),
+        Commands::RandomPath {
+            degree,
+            max_attempts,
+        } => commands::random_path::execute(degree, max_attempts),
+        Commands::Block { offset, file } => commands::block::execute(offset, file),
+        Commands::Bench {
+            articles,
+            main_namespace_only,
+        } => commands::bench::execute(articles, main_namespace_only),
+        Commands::ListDumps {} => commands::list_dumps::execute(),
+        Commands::Hubs { samples, top } => commands::hubs::execute(samples, top),
+        Commands::Verify {} => commands::verify::execute(),
+        Commands::Distance { start, end } => commands::distance::execute(start, end),
+        Commands::SeeAlso {
+            article,
+            ignore_case,
+        } => commands::see_also::execute(article, ignore_case),
+        Commands::CheckTitles { repair } => commands::check_titles::execute(repair),
+        Commands::Stats { top } => commands::stats::execute(top as usize),
     }
 }
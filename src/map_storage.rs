@@ -0,0 +1,127 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Cursor, Read, Seek, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use crate::{atomic_write::write_atomic, data_dir::data_dir};
+
+/// A readable and seekable stream, such as a [`File`].
+pub trait ReadSeek: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+/// Where a [`crate::hierarchical_map::HierarchicalMap`] stores its serialised data.
+/// Paths are relative to the root of the storage, for example `outgoing_links/12.jsonl`.
+pub trait MapStorage: Send + Sync {
+    /// Opens the file at the given path for reading.
+    fn open(&self, path: &Path) -> std::io::Result<Box<dyn ReadSeek>>;
+
+    /// Replaces the file at the given path with the output of `contents`, creating any parent directories.
+    /// If `contents` fails, the file is left as it was.
+    fn write(
+        &self,
+        path: &Path,
+        contents: &mut dyn FnMut(&mut dyn Write) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()>;
+
+    /// Returns the length in bytes of the file at the given path.
+    fn len(&self, path: &Path) -> std::io::Result<u64>;
+
+    /// Removes the file at the given path, if it exists.
+    fn remove(&self, path: &Path) -> std::io::Result<()>;
+
+    /// Returns true if there is a file at the given path.
+    fn is_file(&self, path: &Path) -> bool {
+        self.len(path).is_ok()
+    }
+}
+
+/// Stores data in files inside a directory on disk.
+#[derive(Debug, Clone)]
+pub struct FileStorage {
+    root: PathBuf,
+}
+
+impl FileStorage {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+}
+
+impl Default for FileStorage {
+    /// Stores data in the data directory.
+    fn default() -> Self {
+        Self::new(data_dir().to_owned())
+    }
+}
+
+impl MapStorage for FileStorage {
+    fn open(&self, path: &Path) -> std::io::Result<Box<dyn ReadSeek>> {
+        Ok(Box::new(File::open(self.root.join(path))?))
+    }
+
+    fn write(
+        &self,
+        path: &Path,
+        contents: &mut dyn FnMut(&mut dyn Write) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        write_atomic(&self.root.join(path), |writer| contents(writer))
+    }
+
+    fn len(&self, path: &Path) -> std::io::Result<u64> {
+        let metadata = std::fs::metadata(self.root.join(path))?;
+        if metadata.is_file() {
+            Ok(metadata.len())
+        } else {
+            Err(std::io::Error::other("not a file"))
+        }
+    }
+
+    fn remove(&self, path: &Path) -> std::io::Result<()> {
+        match std::fs::remove_file(self.root.join(path)) {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+}
+
+/// Stores data in memory, so that hierarchical maps can be used without touching the disk, such as in tests.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    files: Arc<Mutex<HashMap<PathBuf, Vec<u8>>>>,
+}
+
+impl MapStorage for MemoryStorage {
+    fn open(&self, path: &Path) -> std::io::Result<Box<dyn ReadSeek>> {
+        match self.files.lock().unwrap().get(path) {
+            Some(contents) => Ok(Box::new(Cursor::new(contents.clone()))),
+            None => Err(std::io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    fn write(
+        &self,
+        path: &Path,
+        contents: &mut dyn FnMut(&mut dyn Write) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let mut buffer = Vec::new();
+        contents(&mut buffer)?;
+        self.files.lock().unwrap().insert(path.to_owned(), buffer);
+        Ok(())
+    }
+
+    fn len(&self, path: &Path) -> std::io::Result<u64> {
+        match self.files.lock().unwrap().get(path) {
+            Some(contents) => Ok(contents.len() as u64),
+            None => Err(std::io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    fn remove(&self, path: &Path) -> std::io::Result<()> {
+        self.files.lock().unwrap().remove(path);
+        Ok(())
+    }
+}
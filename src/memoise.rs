@@ -1,5 +1,5 @@
 use std::{
-    io::{BufReader, BufWriter, Read, Write},
+    io::{BufReader, Read, Write},
     sync::{
         atomic::{AtomicUsize, Ordering},
         Arc,
@@ -13,7 +13,7 @@ use flate2::{
 };
 use serde::{Deserialize, Serialize};
 
-use crate::progress_bar::file_progress_bar;
+use crate::{atomic_write::write_atomic, data_dir::data_dir, progress_bar::file_progress_bar};
 
 /// Stores the result of this function on disk and retrieves it when needed.
 pub fn memoise<T>(
@@ -25,7 +25,8 @@ pub fn memoise<T>(
 where
     T: Serialize + for<'a> Deserialize<'a> + Send + 'static,
 {
-    if let Ok(file) = std::fs::File::open(format!("data/{key}.json{}", if gz { ".gz" } else { "" }))
+    if let Ok(file) =
+        std::fs::File::open(data_dir().join(format!("{key}.json{}", if gz { ".gz" } else { "" })))
     {
         let len = file.metadata()?.len();
         let progress = Arc::new(AtomicUsize::new(0));
@@ -48,33 +49,17 @@ where
         task.join().map_err(|_| anyhow::Error::msg("panic"))?
     } else {
         let result = f()?;
-        let file =
-            std::fs::File::create(format!("data/{key}.json{}", if gz { ".gz" } else { "" }))?;
-
-        if gz {
-            let (reader, mut writer) = pipe::pipe();
-            let task = std::thread::spawn::<_, anyhow::Result<()>>(move || {
-                let mut encoder = GzEncoder::new(reader, Compression::best());
-                let mut writer = BufWriter::new(file);
-                std::io::copy(&mut encoder, &mut writer)?;
-                writer.flush()?;
-                Ok(())
-            });
-            serde_json::to_writer(&mut writer, &result)?;
-            task.join().map_err(|_| anyhow::Error::msg("panic"))??;
-            Ok(result)
-        } else {
-            let (mut reader, mut writer) = pipe::pipe();
-            let task = std::thread::spawn::<_, anyhow::Result<()>>(move || {
-                let mut writer = BufWriter::new(file);
-                std::io::copy(&mut reader, &mut writer)?;
-                writer.flush()?;
-                Ok(())
-            });
-            serde_json::to_writer(&mut writer, &result)?;
-            task.join().map_err(|_| anyhow::Error::msg("panic"))??;
-            Ok(result)
-        }
+        write_atomic(
+            &data_dir().join(format!("{key}.json{}", if gz { ".gz" } else { "" })),
+            |file| {
+                if gz {
+                    compress_into(file, |writer| Ok(serde_json::to_writer(writer, &result)?))
+                } else {
+                    Ok(serde_json::to_writer(file, &result)?)
+                }
+            },
+        )?;
+        Ok(result)
     }
 }
 
@@ -88,7 +73,8 @@ pub fn memoise_bytes<T>(
 where
     T: BytesSerde + Send + 'static,
 {
-    if let Ok(file) = std::fs::File::open(format!("data/{key}.bin{}", if gz { ".gz" } else { "" }))
+    if let Ok(file) =
+        std::fs::File::open(data_dir().join(format!("{key}.bin{}", if gz { ".gz" } else { "" })))
     {
         let len = file.metadata()?.len();
         let progress = Arc::new(AtomicUsize::new(0));
@@ -112,41 +98,77 @@ where
         task.join().map_err(|_| anyhow::Error::msg("panic"))?
     } else {
         let result = f()?;
-        let file = std::fs::File::create(format!("data/{key}.bin{}", if gz { ".gz" } else { "" }))?;
-
-        if gz {
-            let (reader, mut writer) = pipe::pipe();
-            let task = std::thread::spawn::<_, anyhow::Result<()>>(move || {
-                let mut encoder = GzEncoder::new(reader, Compression::best());
-                let mut writer = BufWriter::new(file);
-                std::io::copy(&mut encoder, &mut writer)?;
-                writer.flush()?;
-                Ok(())
-            });
-            result.serialize(&mut writer)?;
-            task.join().map_err(|_| anyhow::Error::msg("panic"))??;
-            Ok(result)
-        } else {
-            let (mut reader, mut writer) = pipe::pipe();
-            let task = std::thread::spawn::<_, anyhow::Result<()>>(move || {
-                let mut writer = BufWriter::new(file);
-                std::io::copy(&mut reader, &mut writer)?;
-                writer.flush()?;
-                Ok(())
-            });
-            result.serialize(&mut writer)?;
-            task.join().map_err(|_| anyhow::Error::msg("panic"))??;
-            Ok(result)
-        }
+        write_atomic(
+            &data_dir().join(format!("{key}.bin{}", if gz { ".gz" } else { "" })),
+            |file| {
+                if gz {
+                    compress_into(file, |writer| result.serialize(writer))
+                } else {
+                    result.serialize(file)
+                }
+            },
+        )?;
+        Ok(result)
     }
 }
 
+/// Gzip-compresses everything that `f` writes into `file`.
+/// The compression runs on another thread, so that it can happen at the same time as the serialisation.
+fn compress_into(
+    file: &mut (impl Write + Send),
+    f: impl FnOnce(&mut pipe::PipeWriter) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    std::thread::scope(|scope| {
+        let (reader, mut writer) = pipe::pipe();
+        let task = scope.spawn::<_, anyhow::Result<()>>(move || {
+            let mut encoder = GzEncoder::new(reader, Compression::best());
+            std::io::copy(&mut encoder, file)?;
+            Ok(())
+        });
+        f(&mut writer)?;
+        // Dropping the writer signals to the compression thread that there is nothing more to compress.
+        drop(writer);
+        task.join().map_err(|_| anyhow::Error::msg("panic"))?
+    })
+}
+
 /// A trait for more efficient serialisation and deserialisation mechanisms.
+///
+/// Implementations that write strings should prefix them with their length in bytes (`str::len`),
+/// not their length in characters, so that multi-byte UTF-8 sequences round-trip losslessly.
 pub trait BytesSerde: Sized {
     fn serialize(&self, writer: &mut impl std::io::Write) -> anyhow::Result<()>;
     fn deserialize(reader: &mut impl std::io::Read) -> anyhow::Result<Self>;
 }
 
+impl BytesSerde for u32 {
+    fn serialize(&self, writer: &mut impl std::io::Write) -> anyhow::Result<()> {
+        Ok(writer.write_all(&self.to_le_bytes())?)
+    }
+
+    fn deserialize(reader: &mut impl std::io::Read) -> anyhow::Result<Self> {
+        let mut bytes = [0; 4];
+        reader.read_exact(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+}
+
+/// A vector is written as its length, followed by each of its elements.
+impl<T: BytesSerde> BytesSerde for Vec<T> {
+    fn serialize(&self, writer: &mut impl std::io::Write) -> anyhow::Result<()> {
+        BytesSerde::serialize(&u32::try_from(self.len())?, writer)?;
+        for item in self {
+            item.serialize(writer)?;
+        }
+        Ok(())
+    }
+
+    fn deserialize(reader: &mut impl std::io::Read) -> anyhow::Result<Self> {
+        let len = <u32 as BytesSerde>::deserialize(reader)?;
+        (0..len).map(|_| T::deserialize(reader)).collect()
+    }
+}
+
 struct ReadProgressHook<R> {
     inner: R,
     progress: Arc<AtomicUsize>,
@@ -1,94 +1,199 @@
 use std::{
-    collections::HashMap,
+    borrow::Cow,
+    cell::Cell,
+    collections::{HashMap, HashSet},
     fmt::Debug,
     fs::File,
     io::{BufRead, BufReader, Read, Seek},
-    path::PathBuf,
-    str::FromStr,
+    ops::RangeInclusive,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, OnceLock,
+    },
 };
 
-use bzip2::bufread::BzDecoder;
+use bzip2::bufread::{BzDecoder, MultiBzDecoder};
 use chrono::{DateTime, FixedOffset};
 use console::style;
 use crossbeam::channel::Receiver;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    binary_search_line::binary_search_line_in_file,
-    commands::download::DumpStatus,
+    binary_search_line::{binary_search_line_in_file, first_line_in_file, last_line_in_file},
+    commands::download::{DownloadManifest, DumpStatus},
+    data_dir::data_dir,
     memoise::memoise,
-    parse::xml::{make_errors_static, parse_element, parse_whitespace, shorten, Element},
+    parse::xml::{make_errors_static, parse_element, shorten, Element},
     progress_bar::normal_progress_bar,
+    titles::{split_namespace, Namespace},
 };
 
 /// Yields some `'static` information about a page given by its ID.
 /// Don't use this function multiple times in quick succession: this opens the index and article files.
+/// To look up many pages, use a [`PageReader`] instead.
 pub fn page_information<T: 'static>(
     dump_status: &DumpStatus,
     id: u32,
     information: impl for<'a> FnOnce(ParsedPage<'a>) -> T,
 ) -> anyhow::Result<T> {
-    let files = dump_status.jobs.articles_multistream_dump.files();
-    for (_, articles) in files.iter().filter(|(file, _)| !file.contains("index")) {
-        let index_url = articles
-            .url
-            .replace("multistream", "multistream-index")
-            .replace(".xml", ".txt")
-            .replace(".bz2", ".txt");
-        let (_, suffix) = index_url.split_once(".txt-").unwrap();
-        let suffix = suffix.strip_suffix(".txt").unwrap();
-        let [start, end]: [&str; 2] = suffix
-            .split(|c: char| !c.is_numeric())
-            .filter(|s| !s.is_empty())
-            .collect::<Vec<_>>()
-            .try_into()
-            .unwrap();
-        let (start, end) = (start.parse::<u32>().unwrap(), end.parse::<u32>().unwrap());
-
-        if start <= id && id <= end {
-            // Binary search through the index file to find the right block to find the page.
-            let mut articles_file =
-                std::fs::File::open(PathBuf::from_str("data")?.join(&articles.url))?;
-            let mut articles_index_file =
-                std::fs::File::open(PathBuf::from_str("data")?.join(&index_url))?;
-
-            let line = binary_search_line_in_file(
-                &mut articles_index_file,
-                |line| {
-                    let (_byte_offset, line) = line.split_once(':').unwrap();
-                    let (article_id, _article_title) = line.split_once(':').unwrap();
-                    article_id.parse().unwrap()
-                },
-                &id,
-            )?
-            .unwrap();
-
-            let (byte_offset, line) = line.split_once(':').unwrap();
-            let (article_id, _article_title) = line.split_once(':').unwrap();
-            let article_id = article_id.parse::<u32>()?;
-            let pages = read_pages(&mut articles_file, byte_offset.parse()?)?;
-            let mut input = pages.as_str();
-            while !input.is_empty() {
-                let (new_input, _) = make_errors_static(parse_whitespace(input))?;
-                let (new_input, page) = make_errors_static(parse_element(new_input))?;
-                let (new_input, _) = make_errors_static(parse_whitespace(new_input))?;
-                input = new_input;
-                let page = ParsedPage::from(page);
-                if page.id == article_id {
-                    return Ok(information(page));
+    PageReader::new(dump_status)?.information(id, information)
+}
+
+/// Looks up individual pages by their ID.
+/// The index and articles files are opened once, and kept open between lookups,
+/// together with the range of IDs that each file contains.
+pub struct PageReader {
+    files: Vec<PageReaderFile>,
+}
+
+struct PageReaderFile {
+    ids: RangeInclusive<u32>,
+    articles_index_file: File,
+    articles_file: File,
+}
+
+impl PageReader {
+    pub fn new(dump_status: &DumpStatus) -> anyhow::Result<Self> {
+        let mut files = Vec::new();
+        for (_, articles) in dump_status
+            .jobs
+            .articles_multistream_dump
+            .files()
+            .iter()
+            .filter(|(file, _)| !file.contains("index"))
+        {
+            let mut articles_index_file = File::open(data_dir().join(index_url(&articles.url)))?;
+            let Some((start, end)) = index_id_range(&mut articles_index_file)? else {
+                continue;
+            };
+            files.push(PageReaderFile {
+                ids: start..=end,
+                articles_index_file,
+                articles_file: File::open(data_dir().join(&articles.url))?,
+            });
+        }
+        Ok(Self { files })
+    }
+
+    /// Yields some `'static` information about the page with the given ID.
+    pub fn information<T: 'static>(
+        &mut self,
+        id: u32,
+        information: impl for<'a> FnOnce(ParsedPage<'a>) -> T,
+    ) -> anyhow::Result<T> {
+        let file = self
+            .files
+            .iter_mut()
+            .find(|file| file.ids.contains(&id))
+            .ok_or(PageError::IdOutOfRange(id))?;
+
+        // Binary search through the index file to find the right block to find the page.
+        // The key function can't fail, so we remember the first malformed line and report it afterwards.
+        let malformed = Cell::new(None);
+        let line = binary_search_line_in_file(
+            &mut file.articles_index_file,
+            |line| match parse_index_line(line) {
+                Ok((_byte_offset, article_id)) => article_id,
+                Err(err) => {
+                    malformed.set(Some(err));
+                    0
                 }
+            },
+            &id,
+        )?;
+        if let Some(err) = malformed.take() {
+            return Err(err.into());
+        }
+        let line = line.ok_or(PageError::NotInIndex(id))?;
+
+        let (byte_offset, article_id) = parse_index_line(&line)?;
+        let mut information = Some(information);
+        let mut result = None;
+        for_each_page_in_block(&mut file.articles_file, byte_offset, |page| {
+            if page.id == article_id {
+                result = information.take().map(|information| information(page));
+                Ok(false)
+            } else {
+                Ok(true)
             }
-            break;
+        })?;
+        Ok(result.ok_or(PageError::NotFoundInBlock(id))?)
+    }
+}
+
+/// The ways in which looking up a single page by its ID can fail.
+/// These are returned inside an [`anyhow::Error`], so callers can recover from them with `downcast_ref`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PageError {
+    /// No articles file covers this ID.
+    IdOutOfRange(u32),
+    /// The ID lies in the range of an articles file, but its index has no page with this ID.
+    NotInIndex(u32),
+    /// The index points to a block that does not contain the page with this ID.
+    NotFoundInBlock(u32),
+    /// A line of an index file could not be parsed.
+    ParseFailed(String),
+}
+
+impl PageError {
+    /// Returns true if the page simply does not exist, rather than the data being malformed.
+    pub fn is_missing(&self) -> bool {
+        !matches!(self, PageError::ParseFailed(_))
+    }
+}
+
+impl std::fmt::Display for PageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PageError::IdOutOfRange(id) => write!(f, "id {id} not in range"),
+            PageError::NotInIndex(id) => write!(f, "id {id} not found in the index"),
+            PageError::NotFoundInBlock(id) => write!(f, "id {id} not found in its block"),
+            PageError::ParseFailed(line) => write!(f, "malformed index line {line}"),
         }
     }
-    panic!("id {id} not in range")
 }
 
-/// Yields some `'static` information about every page.
+impl std::error::Error for PageError {}
+
+/// Splits a line of an index file into the byte offset of its block and its article ID.
+fn parse_index_line(line: &str) -> Result<(u64, u32), PageError> {
+    let malformed = || PageError::ParseFailed(line.to_owned());
+    let (byte_offset, line) = line.split_once(':').ok_or_else(malformed)?;
+    let (article_id, _article_title) = line.split_once(':').ok_or_else(malformed)?;
+    Ok((
+        byte_offset.parse().map_err(|_| malformed())?,
+        article_id.parse().map_err(|_| malformed())?,
+    ))
+}
+
+/// Determines when [`page_stream`] stops yielding pages.
+#[derive(Debug, Clone, Copy)]
+pub enum StopCondition {
+    /// Stop after this many pages have been yielded.
+    /// Since each articles file is processed by its own thread, the set of pages processed is nondeterministic
+    /// unless every page is processed.
+    Count(u64),
+    /// Process exactly the pages whose ID is at most this value.
+    /// Unlike [`StopCondition::Count`], the set of pages processed does not depend on thread scheduling,
+    /// which makes this suitable for reproducible partial runs, such as in testing.
+    MaxId(u32),
+}
+
+impl StopCondition {
+    /// Process every page.
+    pub const ALL: Self = StopCondition::Count(u64::MAX);
+}
+
+/// Yields some `'static` information about every page, until the stop condition is reached.
 /// The `capacity` is the capacity of the internal buffer.
+///
+/// If `main_namespace_only` is true, only pages in the main namespace are yielded,
+/// and only these count towards [`StopCondition::Count`].
+/// Blocks whose index entries contain no main namespace pages are skipped without being decompressed.
 pub fn page_stream<T: Send + Sync + 'static>(
-    cutoff: u64,
+    stop: StopCondition,
     capacity: usize,
+    main_namespace_only: bool,
     message: String,
     information: impl for<'a> Fn(ParsedPage<'a>) -> T + Clone + Send + 'static,
 ) -> anyhow::Result<Receiver<T>> {
@@ -97,17 +202,30 @@ pub fn page_stream<T: Send + Sync + 'static>(
     let num_articles = count_articles(&dump_status)?;
     num_articles.summarise();
 
-    let max = if cutoff < num_articles.total() {
-        println!(
-            "Processing the first {} articles",
-            style(cutoff).bold().bright()
-        );
-        cutoff
-    } else {
-        num_articles.total()
+    // If we're processing every page, we never stop early, since the article count only includes pages
+    // from articles files whose index is present.
+    let (max, limit, max_id) = match stop {
+        StopCondition::Count(cutoff) if cutoff < num_articles.total() => {
+            println!(
+                "Processing the first {} articles",
+                style(cutoff).bold().bright()
+            );
+            (cutoff, Some(cutoff), None)
+        }
+        StopCondition::Count(_) => (num_articles.total(), None, None),
+        StopCondition::MaxId(max_id) => {
+            println!(
+                "Processing articles with ID at most {}",
+                style(max_id).bold().bright()
+            );
+            (num_articles.total(), None, Some(max_id))
+        }
     };
 
     let progress_bar = normal_progress_bar(max).with_message(message);
+    // If we stop after a number of pages, the progress bar counts the pages yielded, since pages filtered out
+    // don't bring us closer to stopping. Otherwise, it counts every page processed, including skipped blocks.
+    let count_yielded = limit.is_some();
 
     let (tx, rx) = crossbeam::channel::bounded(capacity);
 
@@ -118,46 +236,69 @@ pub fn page_stream<T: Send + Sync + 'static>(
         let tx = tx.clone();
         let information = information.clone();
         std::thread::spawn(move || {
-            let mut articles_file =
-                std::fs::File::open(PathBuf::from_str("data")?.join(&articles.url))?;
-            let articles_index_file = std::fs::File::open(
-                PathBuf::from_str("data")?.join(
-                    articles
-                        .url
-                        .replace("multistream", "multistream-index")
-                        .replace(".xml", ".txt")
-                        .replace(".bz2", ".txt"),
-                ),
-            )?;
+            let limit_reached = || limit.is_some_and(|limit| progress_bar.position() >= limit);
+            // Sends this page if it passes the filters, and returns false if we should stop.
+            let handle_page = |page: ParsedPage<'_>| -> anyhow::Result<bool> {
+                let past_max_id = max_id.is_some_and(|max_id| page.id > max_id);
+                if !past_max_id && (!main_namespace_only || page.namespace == 0) {
+                    tx.send(information(page))?;
+                    if count_yielded {
+                        progress_bar.inc(1);
+                    }
+                }
+                if !count_yielded {
+                    progress_bar.inc(1);
+                }
+                Ok(!limit_reached())
+            };
 
-            let lines = BufReader::new(articles_index_file).lines();
-            let mut latest_offset = 0;
+            let mut articles_file = std::fs::File::open(data_dir().join(&articles.url))?;
+            let articles_index_file = match std::fs::File::open(
+                data_dir().join(index_url(&articles.url)),
+            ) {
+                Ok(file) => file,
+                Err(err) => {
+                    progress_bar.suspend(|| {
+                        eprintln!(
+                            "{} could not open the index for {}, so reading it from the start: {err}",
+                            style("warning:").yellow().bold(),
+                            articles.url
+                        )
+                    });
+                    return for_each_page_linear(articles_file, |page| {
+                        if max_id.is_some_and(|max_id| page.id > max_id) {
+                            // Pages are sorted by ID, so every remaining page is also past the maximum ID.
+                            return Ok(false);
+                        }
+                        handle_page(page)
+                    });
+                }
+            };
 
-            for line in lines {
-                let line = line?;
-                if line.is_empty() {
-                    continue;
+            let blocks = read_index_blocks(articles_index_file)?;
+            for (i, block) in blocks.iter().enumerate() {
+                if max_id.is_some_and(|max_id| block.first_id > max_id) {
+                    // Blocks are sorted by ID, so every remaining block is also past the maximum ID.
+                    progress_bar.inc(blocks[i..].iter().map(|block| block.pages).sum());
+                    return Ok(());
                 }
 
-                let (byte_offset, line) = line.split_once(':').unwrap();
-                let (_article_id, _article_title) = line.split_once(':').unwrap();
-                let byte_offset = byte_offset.parse::<u64>()?;
-
-                if byte_offset > latest_offset {
-                    latest_offset = byte_offset;
-                    let pages = read_pages(&mut articles_file, byte_offset)?;
-                    let mut input = pages.as_str();
-                    while !input.is_empty() {
-                        let (new_input, _) = make_errors_static(parse_whitespace(input))?;
-                        let (new_input, page) = make_errors_static(parse_element(new_input))?;
-                        let (new_input, _) = make_errors_static(parse_whitespace(new_input))?;
-                        input = new_input;
-                        tx.send(information(ParsedPage::from(page)))?;
-                        progress_bar.inc(1);
-                        if progress_bar.position() >= max {
-                            return Ok(());
-                        }
+                if main_namespace_only && block.main_namespace_pages == 0 {
+                    // Skip the entire block without decompressing it.
+                    if !count_yielded {
+                        progress_bar.inc(block.pages);
                     }
+                    continue;
+                }
+
+                let mut stopped = false;
+                for_each_page_in_block(&mut articles_file, block.byte_offset, |page| {
+                    let keep_going = handle_page(page)?;
+                    stopped = !keep_going;
+                    Ok(keep_going)
+                })?;
+                if stopped {
+                    return Ok(());
                 }
             }
 
@@ -168,9 +309,131 @@ pub fn page_stream<T: Send + Sync + 'static>(
     Ok(rx)
 }
 
+/// Returns the URL of the (decompressed) index file associated to the given articles file.
+pub fn index_url(articles_url: &str) -> String {
+    articles_url
+        .replace("multistream", "multistream-index")
+        .replace(".xml", ".txt")
+        .replace(".bz2", ".txt")
+}
+
+/// Returns the smallest and largest article IDs in the given index file,
+/// by reading its first and last lines, or [`None`] if the file is empty.
+///
+/// The articles in each index file are sorted by ID, so this is the range of IDs it covers.
+pub fn index_id_range(articles_index_file: &mut File) -> anyhow::Result<Option<(u32, u32)>> {
+    let first_line = first_line_in_file(articles_index_file)?;
+    let last_line = last_line_in_file(articles_index_file)?;
+    match (first_line, last_line) {
+        (Some(first_line), Some(last_line)) => Ok(Some((
+            parse_index_line(&first_line)?.1,
+            parse_index_line(&last_line)?.1,
+        ))),
+        _ => Ok(None),
+    }
+}
+
+/// A single bz2 substream of an articles file, as described by its index file.
+#[derive(Debug, Clone)]
+pub struct IndexBlock {
+    /// The byte offset of this block in the articles file.
+    pub byte_offset: u64,
+    /// The ID of the first page in this block.
+    pub first_id: u32,
+    /// The number of pages in this block.
+    pub pages: u64,
+    /// The number of pages in this block whose title has no namespace.
+    pub main_namespace_pages: u64,
+}
+
+/// Reads an index file, grouping its lines into the blocks of the corresponding articles file.
+pub fn read_index_blocks(articles_index_file: impl Read) -> anyhow::Result<Vec<IndexBlock>> {
+    let mut blocks = Vec::<IndexBlock>::new();
+    for line in BufReader::new(articles_index_file).lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+
+        let (byte_offset, line) = line.split_once(':').unwrap();
+        let (article_id, article_title) = line.split_once(':').unwrap();
+        let byte_offset = byte_offset.parse::<u64>()?;
+        let is_main_namespace = split_namespace(article_title).0.is_none();
+
+        match blocks.last_mut() {
+            Some(block) if block.byte_offset == byte_offset => {
+                block.pages += 1;
+                block.main_namespace_pages += is_main_namespace as u64;
+            }
+            _ => blocks.push(IndexBlock {
+                byte_offset,
+                first_id: article_id.parse()?,
+                pages: 1,
+                main_namespace_pages: is_main_namespace as u64,
+            }),
+        }
+    }
+    Ok(blocks)
+}
+
+/// Reads the namespaces listed in the `<siteinfo>` block at the start of the articles dump.
+/// This block lives in its own stream at the start of each articles file, so we only need to decompress that stream.
+pub fn read_namespaces(dump_status: &DumpStatus) -> anyhow::Result<Vec<Namespace>> {
+    memoise("namespaces", "Reading namespaces", false, || {
+        let files = dump_status.jobs.articles_multistream_dump.files();
+        let (_, articles) = files
+            .iter()
+            .find(|(file, _)| !file.contains("index"))
+            .ok_or_else(|| anyhow::Error::msg("no articles file found"))?;
+        let mut articles_file = File::open(data_dir().join(&articles.url))?;
+        parse_namespaces(&read_pages(&mut articles_file, 0)?)
+    })
+}
+
+/// Parses the namespaces listed in the `<siteinfo>` block in the given text.
+fn parse_namespaces(header: &str) -> anyhow::Result<Vec<Namespace>> {
+    let siteinfo = header
+        .find("<siteinfo>")
+        .ok_or_else(|| anyhow::Error::msg("articles file has no siteinfo block"))?;
+    let (_, siteinfo) = make_errors_static(parse_element(&header[siteinfo..]))?;
+
+    siteinfo
+        .find("namespaces")?
+        .children
+        .iter()
+        .map(|namespace| {
+            Ok(Namespace {
+                id: namespace.get_attribute("key")?.parse()?,
+                name: namespace.text.to_string(),
+                first_letter: namespace.get_attribute("case")? == "first-letter",
+            })
+        })
+        .collect()
+}
+
+/// Reads the status of the current dump.
+/// Fails if the download of the dump did not complete, since reading it would then fail in confusing ways.
 pub fn get_dump_status() -> anyhow::Result<DumpStatus> {
+    let dump_status = read_dump_status()?;
+    if let Some(manifest) = DownloadManifest::load()? {
+        let missing = manifest.missing_files(&dump_status);
+        if !missing.is_empty() {
+            return Err(anyhow::Error::msg(format!(
+                "the download of version {} is incomplete: {} files never finished downloading ({}); \
+                run the download command again to resume it",
+                dump_status.date.as_deref().unwrap_or("unknown"),
+                missing.len(),
+                missing.join(", ")
+            )));
+        }
+    }
+    Ok(dump_status)
+}
+
+/// Reads the status of the current dump, without checking that all of its files were downloaded.
+pub fn read_dump_status() -> anyhow::Result<DumpStatus> {
     Ok(serde_json::from_str::<DumpStatus>(
-        &std::fs::read_to_string("data/current_dump.json")?,
+        &std::fs::read_to_string(data_dir().join("current_dump.json"))?,
     )?)
 }
 
@@ -187,8 +450,16 @@ pub fn count_articles(dump_status: &DumpStatus) -> anyhow::Result<ArticleCount>
         )
         .with_message("Counting articles");
         for (file, articles) in files.iter().filter(|(file, _)| file.contains("index")) {
-            let articles_index_file =
-                std::fs::File::open(PathBuf::from_str("data")?.join(&articles.url))?;
+            let articles_index_file = match std::fs::File::open(data_dir().join(&articles.url)) {
+                Ok(file) => file,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                    // `page_stream` reads the corresponding articles file without its index,
+                    // so its pages are simply not included in the count.
+                    progress_bar.inc(1);
+                    continue;
+                }
+                Err(err) => return Err(err.into()),
+            };
             let lines = BufReader::new(articles_index_file).lines();
             let mut num_articles = 0u64;
             for line in lines {
@@ -227,9 +498,76 @@ impl ArticleCount {
     }
 }
 
+/// Calls `f` on every page in the supplied articles file, decompressing it from the start.
+/// This is much slower than using the index, but works even if the index is missing.
+/// Stops early if `f` returns false.
+fn for_each_page_linear(
+    articles_file: File,
+    f: impl FnMut(ParsedPage<'_>) -> anyhow::Result<bool>,
+) -> anyhow::Result<()> {
+    for_each_page(
+        BufReader::new(MultiBzDecoder::new(BufReader::new(articles_file))),
+        f,
+    )
+}
+
+/// Calls `f` on every page in the block at the given byte offset in the supplied articles file.
+/// The block is decompressed as it is parsed, so only one page of it is held in memory at a time.
+/// Stops early if `f` returns false.
+pub fn for_each_page_in_block(
+    articles_file: &mut File,
+    byte_offset: u64,
+    f: impl FnMut(ParsedPage<'_>) -> anyhow::Result<bool>,
+) -> anyhow::Result<()> {
+    articles_file.seek(std::io::SeekFrom::Start(byte_offset))?;
+    for_each_page(
+        BufReader::new(BzDecoder::new(BufReader::new(articles_file))),
+        f,
+    )
+}
+
+/// Calls `f` on every page read from the decompressed XML, buffering only one page at a time.
+/// Each page starts and ends on its own line, and any `<` in the page text is escaped,
+/// so we can find the pages line by line, skipping anything between them such as the `<siteinfo>` prologue.
+/// Pages with malformed fields are skipped with a warning, since one malformed page shouldn't abort a whole run.
+/// Stops early if `f` returns false.
+pub fn for_each_page(
+    mut reader: impl BufRead,
+    mut f: impl FnMut(ParsedPage<'_>) -> anyhow::Result<bool>,
+) -> anyhow::Result<()> {
+    let mut page = String::new();
+    loop {
+        let line_start = page.len();
+        if reader.read_line(&mut page)? == 0 {
+            break;
+        }
+        match page[line_start..].trim() {
+            "<page>" => page.replace_range(..line_start, ""),
+            "</page>" => {
+                let (_, element) = make_errors_static(parse_element(page.trim()))?;
+                match ParsedPage::try_from(element) {
+                    Ok(parsed) => {
+                        if !f(parsed)? {
+                            break;
+                        }
+                    }
+                    // Skip the page.
+                    Err(err) => eprintln!(
+                        "{} skipping malformed page: {err:#}",
+                        style("warning:").yellow().bold()
+                    ),
+                }
+                page.clear();
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
 /// Reads the pages at the given byte offset in the supplied articles file.
 /// There are normally 100 pages in each substream.
-fn read_pages(articles_file: &mut File, byte_offset: u64) -> anyhow::Result<String> {
+pub fn read_pages(articles_file: &mut File, byte_offset: u64) -> anyhow::Result<String> {
     articles_file.seek(std::io::SeekFrom::Start(byte_offset))?;
     let mut decoder = BzDecoder::new(BufReader::new(articles_file));
     let mut output = String::new();
@@ -240,36 +578,73 @@ fn read_pages(articles_file: &mut File, byte_offset: u64) -> anyhow::Result<Stri
 /// We use custom XML deserialisation for pages because of how important efficiency is for our use-case.
 #[derive(Default, Debug)]
 pub struct ParsedPage<'a> {
-    pub title: &'a str,
+    pub title: Cow<'a, str>,
     pub namespace: u32,
     pub id: u32,
-    pub redirect: Option<&'a str>,
+    pub redirect: Option<Cow<'a, str>>,
     pub revision: ParsedRevision<'a>,
 }
 
 #[derive(Default)]
 pub struct ParsedRevision<'a> {
     pub id: u32,
+    /// The ID of the previous revision of the page, or [`None`] if this is its first revision.
+    pub parent_id: Option<u32>,
     pub timestamp: DateTime<FixedOffset>,
-    pub model: &'a str,
-    pub format: &'a str,
-    pub text: &'a str,
+    /// The author of the revision, or [`None`] if their details have been deleted.
+    pub contributor: Option<ParsedContributor<'a>>,
+    /// The edit summary, or [`None`] if there is none or it has been deleted.
+    pub comment: Option<Cow<'a, str>>,
+    /// Whether the revision was marked as a minor edit.
+    pub minor: bool,
+    pub model: Cow<'a, str>,
+    pub format: Cow<'a, str>,
+    pub text: Cow<'a, str>,
+    /// The base 36 SHA1 hash of the text, or [`None`] if it was not exported.
+    pub sha1: Option<Cow<'a, str>>,
+}
+
+/// The author of a revision.
+/// Registered users have a username and an ID, and anonymous users have only an IP address.
+#[derive(Default, Debug)]
+pub struct ParsedContributor<'a> {
+    pub username: Option<Cow<'a, str>>,
+    pub id: Option<u32>,
+    pub ip: Option<Cow<'a, str>>,
 }
 
-impl<'a> From<Element<'a>> for ParsedPage<'a> {
-    fn from(value: Element<'a>) -> Self {
+impl<'a> TryFrom<Element<'a>> for ParsedPage<'a> {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Element<'a>) -> anyhow::Result<Self> {
         let mut result = Self::default();
         for child in value.children {
-            match child.name {
-                "title" => result.title = child.text,
-                "ns" => result.namespace = child.text.parse().unwrap(),
-                "id" => result.id = child.text.parse().unwrap(),
-                "redirect" => result.redirect = Some(child.get_attribute("title").unwrap()),
-                "revision" => result.revision = ParsedRevision::from(child),
-                _ => todo!("unrecognised page child {}", child.summarise()),
+            // The title and ID come first in the export, so we can say which page was malformed.
+            if let Err(err) = result.parse_child(child) {
+                return Err(
+                    err.context(format!("page {} with title {:?}", result.id, result.title))
+                );
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl<'a> ParsedPage<'a> {
+    fn parse_child(&mut self, child: Element<'a>) -> anyhow::Result<()> {
+        match child.name {
+            "title" => self.title = child.text,
+            "ns" => self.namespace = child.text.parse()?,
+            "id" => self.id = child.text.parse()?,
+            "redirect" => {
+                self.redirect = Some(html_escape::decode_html_entities(
+                    child.get_attribute("title")?,
+                ))
             }
+            "revision" => self.revision = ParsedRevision::parse(child, self)?,
+            _ => unrecognised_child("page", &child),
         }
-        result
+        Ok(())
     }
 }
 
@@ -277,28 +652,244 @@ impl<'a> Debug for ParsedRevision<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("ParsedRevision")
             .field("id", &self.id)
+            .field("parent_id", &self.parent_id)
             .field("timestamp", &self.timestamp)
+            .field("contributor", &self.contributor)
+            .field("comment", &self.comment)
+            .field("minor", &self.minor)
             .field("model", &self.model)
             .field("format", &self.format)
-            .field("text", &shorten(self.text.to_owned()))
+            .field("text", &shorten(self.text.to_string()))
+            .field("sha1", &self.sha1)
             .finish()
     }
 }
 
-impl<'a> From<Element<'a>> for ParsedRevision<'a> {
-    fn from(value: Element<'a>) -> Self {
+impl<'a> ParsedRevision<'a> {
+    /// Parses the revision of the given page, which is only used to report malformed fields.
+    fn parse(value: Element<'a>, page: &ParsedPage<'_>) -> anyhow::Result<Self> {
         let mut result = Self::default();
         for child in value.children {
             match child.name {
-                "id" => result.id = child.text.parse().unwrap(),
-                "timestamp" => result.timestamp = DateTime::parse_from_rfc3339(child.text).unwrap(),
+                "id" => result.id = child.text.parse()?,
+                "parentid" => result.parent_id = Some(child.text.parse()?),
+                "timestamp" => match DateTime::parse_from_rfc3339(&child.text) {
+                    Ok(timestamp) => result.timestamp = timestamp,
+                    Err(err) => {
+                        // Leave the timestamp unset rather than skipping the whole page.
+                        eprintln!(
+                            "{} page {} with title {:?} has malformed timestamp {:?}, so leaving it unset: {err}",
+                            style("warning:").yellow().bold(),
+                            page.id,
+                            page.title,
+                            child.text
+                        )
+                    }
+                },
                 "model" => result.model = child.text,
                 "format" => result.format = child.text,
                 "text" => result.text = child.text,
-                "parentid" | "contributor" | "comment" | "origin" | "sha1" | "minor" => {}
-                _ => todo!("unrecognised revision child {}", child.summarise()),
+                // Deleted details are replaced by an empty element with a `deleted` attribute.
+                "contributor" if child.get_attribute("deleted").is_err() => {
+                    result.contributor = Some(ParsedContributor::try_from(child)?)
+                }
+                "comment" if child.get_attribute("deleted").is_err() => {
+                    result.comment = Some(child.text)
+                }
+                "sha1" if !child.text.is_empty() => result.sha1 = Some(child.text),
+                "minor" => result.minor = true,
+                "contributor" | "comment" | "sha1" | "origin" => {}
+                _ => unrecognised_child("revision", &child),
             }
         }
-        result
+        Ok(result)
+    }
+}
+
+impl<'a> TryFrom<Element<'a>> for ParsedContributor<'a> {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Element<'a>) -> anyhow::Result<Self> {
+        let mut result = Self::default();
+        for child in value.children {
+            match child.name {
+                "username" => result.username = Some(child.text),
+                "id" => result.id = Some(child.text.parse()?),
+                "ip" => result.ip = Some(child.text),
+                _ => unrecognised_child("contributor", &child),
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// Whether to panic on elements of a page that we don't recognise, instead of skipping them.
+static STRICT_PARSING: AtomicBool = AtomicBool::new(false);
+
+/// Panics on any element of a page that we don't recognise, instead of warning about it and skipping it.
+/// This is useful when developing, to notice new fields in the export format.
+pub fn enable_strict_parsing() {
+    STRICT_PARSING.store(true, Ordering::SeqCst);
+}
+
+/// Skips a child element that we don't recognise, warning about it the first time we see it.
+/// New versions of the export format may add fields, which we can safely ignore.
+fn unrecognised_child(parent: &str, child: &Element<'_>) {
+    if STRICT_PARSING.load(Ordering::SeqCst) {
+        panic!("unrecognised {parent} child {}", child.summarise());
+    }
+
+    static WARNED: OnceLock<Mutex<HashSet<(String, String)>>> = OnceLock::new();
+    let newly_seen = WARNED
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap()
+        .insert((parent.to_owned(), child.name.to_owned()));
+    if newly_seen {
+        eprintln!(
+            "{} skipping unrecognised {parent} child {}",
+            style("warning:").yellow().bold(),
+            child.name
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_blocks_count_main_namespace_pages() {
+        let index = "\
+600:10:Anarchism
+600:12:Category:Anarchism
+600:13:Nineteen Eighty-Four: The Musical
+4200:20:Template:Infobox
+4200:21:Category:Politics
+9000:30:Albedo
+";
+        let blocks = read_index_blocks(index.as_bytes()).unwrap();
+        let summary = blocks
+            .iter()
+            .map(|block| {
+                (
+                    block.byte_offset,
+                    block.first_id,
+                    block.pages,
+                    block.main_namespace_pages,
+                )
+            })
+            .collect::<Vec<_>>();
+        // The second block has no main namespace pages, so it can be skipped when only those are wanted.
+        assert_eq!(
+            summary,
+            vec![(600, 10, 3, 2), (4200, 20, 2, 0), (9000, 30, 1, 1)]
+        );
+    }
+
+    #[test]
+    fn siteinfo_namespaces_are_parsed() {
+        let header = r#"<mediawiki>
+  <siteinfo>
+    <sitename>Wikipedia</sitename>
+    <dbname>dewiki</dbname>
+    <namespaces>
+      <namespace key="-1" case="first-letter">Spezial</namespace>
+      <namespace key="0" case="first-letter" />
+      <namespace key="2" case="first-letter">Benutzer</namespace>
+      <namespace key="14" case="first-letter">Kategorie</namespace>
+      <namespace key="828" case="case-sensitive">Modul</namespace>
+    </namespaces>
+  </siteinfo>
+"#;
+        let namespaces = parse_namespaces(header).unwrap();
+        let summary = namespaces
+            .iter()
+            .map(|namespace| {
+                (
+                    namespace.id,
+                    namespace.name.as_str(),
+                    namespace.first_letter,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                (-1, "Spezial", true),
+                (0, "", true),
+                (2, "Benutzer", true),
+                (14, "Kategorie", true),
+                (828, "Modul", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_timestamps_do_not_skip_pages() {
+        let xml = "\
+  <siteinfo>
+  </siteinfo>
+  <page>
+    <title>Anarchism</title>
+    <ns>0</ns>
+    <id>12</id>
+    <revision>
+      <id>100</id>
+      <timestamp>yesterday</timestamp>
+      <text>See [[Albedo]].</text>
+    </revision>
+  </page>
+  <page>
+    <title>Albedo</title>
+    <ns>0</ns>
+    <id>39</id>
+    <revision>
+      <id>101</id>
+      <timestamp>2024-01-01T00:00:00Z</timestamp>
+      <text>Light.</text>
+    </revision>
+  </page>
+";
+        let mut pages = Vec::new();
+        for_each_page(xml.as_bytes(), |page| {
+            pages.push((
+                page.id,
+                page.title.into_owned(),
+                page.revision.timestamp.timestamp(),
+            ));
+            Ok(true)
+        })
+        .unwrap();
+        assert_eq!(
+            pages,
+            vec![
+                (12, "Anarchism".to_owned(), 0),
+                (39, "Albedo".to_owned(), 1704067200),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_pages_are_skipped() {
+        let xml = "\
+  <page>
+    <title>Anarchism</title>
+    <ns>zero</ns>
+    <id>12</id>
+  </page>
+  <page>
+    <title>Albedo</title>
+    <ns>0</ns>
+    <id>39</id>
+  </page>
+";
+        let mut ids = Vec::new();
+        for_each_page(xml.as_bytes(), |page| {
+            ids.push(page.id);
+            Ok(true)
+        })
+        .unwrap();
+        assert_eq!(ids, vec![39]);
     }
 }
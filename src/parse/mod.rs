@@ -1,3 +1,4 @@
 pub mod parse_html_index;
 pub mod xml;
+pub mod sql;
 pub mod wikitext;
@@ -0,0 +1,125 @@
+use std::borrow::Cow;
+
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, take_while, take_while1},
+    combinator::map,
+    IResult,
+};
+
+use super::xml::{make_errors_static, parse_whitespace};
+
+/// A single value in a tuple of an SQL `INSERT` statement, as found in the Wikimedia SQL dumps.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SqlValue<'a> {
+    Null,
+    Number(&'a str),
+    String(Cow<'a, str>),
+}
+
+impl<'a> SqlValue<'a> {
+    pub fn as_number<T: std::str::FromStr>(&self) -> Option<T> {
+        match self {
+            SqlValue::Number(number) => number.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            SqlValue::String(string) => Some(string),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a line of the form ``INSERT INTO `table` VALUES (1,'a'),(2,'b');``, returning the list of tuples.
+/// If the line is not an `INSERT` statement (for example, it's a comment or a `CREATE TABLE` statement),
+/// this returns [`None`].
+pub fn parse_insert_values(line: &str) -> anyhow::Result<Option<Vec<Vec<SqlValue<'_>>>>> {
+    let Some(line) = line.strip_prefix("INSERT INTO ") else {
+        return Ok(None);
+    };
+    let Some((_table, mut input)) = line.split_once(" VALUES ") else {
+        return Ok(None);
+    };
+
+    let mut tuples = Vec::new();
+    loop {
+        let (new_input, tuple) = make_errors_static(parse_tuple(input))?;
+        tuples.push(tuple);
+        let (new_input, ()) = make_errors_static(parse_whitespace(new_input))?;
+        match new_input.chars().next() {
+            Some(',') => input = &new_input[1..],
+            _ => break,
+        }
+    }
+
+    Ok(Some(tuples))
+}
+
+fn parse_tuple(input: &str) -> IResult<&str, Vec<SqlValue<'_>>> {
+    let (mut input, _) = tag("(")(input)?;
+    let mut values = Vec::new();
+    loop {
+        let (new_input, value) = parse_value(input)?;
+        values.push(value);
+        if let Some(new_input) = new_input.strip_prefix(',') {
+            input = new_input;
+        } else {
+            let (new_input, _) = tag(")")(new_input)?;
+            return Ok((new_input, values));
+        }
+    }
+}
+
+fn parse_value(input: &str) -> IResult<&str, SqlValue<'_>> {
+    alt((
+        map(tag("NULL"), |_| SqlValue::Null),
+        map(parse_string, SqlValue::String),
+        map(
+            take_while1(|c: char| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')),
+            SqlValue::Number,
+        ),
+    ))(input)
+}
+
+/// Parses a single-quoted string, processing the backslash escapes that `mysqldump` produces.
+/// Strings without escapes are borrowed from the input.
+fn parse_string(input: &str) -> IResult<&str, Cow<'_, str>> {
+    let (input, _) = tag("'")(input)?;
+    let (mut input, prefix) = take_while(|c: char| c != '\'' && c != '\\')(input)?;
+    if let Some(input) = input.strip_prefix('\'') {
+        return Ok((input, Cow::Borrowed(prefix)));
+    }
+
+    let mut output = prefix.to_owned();
+    loop {
+        let mut chars = input.chars();
+        match chars.next() {
+            Some('\'') => return Ok((chars.as_str(), Cow::Owned(output))),
+            Some('\\') => {
+                match chars.next() {
+                    Some('0') => output.push('\0'),
+                    Some('n') => output.push('\n'),
+                    Some('r') => output.push('\r'),
+                    Some('t') => output.push('\t'),
+                    Some('Z') => output.push('\x1a'),
+                    Some(c) => output.push(c),
+                    None => break,
+                }
+                input = chars.as_str();
+            }
+            Some(c) => {
+                output.push(c);
+                input = chars.as_str();
+            }
+            None => break,
+        }
+    }
+
+    Err(nom::Err::Error(nom::error::Error {
+        input,
+        code: nom::error::ErrorKind::Eof,
+    }))
+}
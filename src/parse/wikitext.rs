@@ -2,30 +2,251 @@ use std::{borrow::Cow, fmt::Display};
 
 use crate::titles::canonicalise_wikilink;
 
-/// Finds a list of all links in this wikitext file.
-/// This doesn't process nested links well, possibly giving shorter-than-expected `text`,
-/// but will always give the correct `target`.
-pub fn find_links(text: &str) -> Vec<Wikilink> {
+/// Finds a list of all links in this wikitext file, including links nested inside the text of other links.
+///
+/// Links inside HTML comments `<!-- ... -->`, `<nowiki>...</nowiki>` blocks or `<pre>...</pre>` blocks are ignored,
+/// since they are not rendered as links.
+pub fn find_links(text: &str) -> Vec<Wikilink<'_>> {
+    match strip_unrendered(text) {
+        Cow::Borrowed(text) => find_links_uncommented(text),
+        Cow::Owned(text) => find_links_uncommented(&text)
+            .into_iter()
+            .map(Wikilink::to_owned)
+            .collect(),
+    }
+}
+
+/// The tags whose contents are removed by [`strip_unrendered`], in lowercase.
+const UNRENDERED_TAGS: [&str; 2] = ["nowiki", "pre"];
+
+/// Removes all HTML comments `<!-- ... -->`, `<nowiki>...</nowiki>` blocks and `<pre>...</pre>` blocks from this wikitext.
+/// Tag names are case-insensitive, and tags may have attributes, as in `<pre class="x">` or `<nowiki >`.
+/// An unterminated region extends to the end of the text.
+/// Self-closing `<nowiki/>` tags contain nothing, so are left alone.
+///
+/// We scan the text once from start to finish, skipping over each region as soon as we find it.
+pub fn strip_unrendered(text: &str) -> Cow<'_, str> {
+    let mut output = String::new();
+    // Everything before `copied` has already been copied to the output or removed.
+    let mut copied = 0;
+    let mut search = 0;
+    while let Some(offset) = text[search..].find('<') {
+        let start = search + offset;
+        match unrendered_region_len(&text[start..]) {
+            Some(len) => {
+                output.push_str(&text[copied..start]);
+                copied = start + len;
+                search = copied;
+            }
+            None => search = start + 1,
+        }
+    }
+    if copied == 0 {
+        return Cow::Borrowed(text);
+    }
+    output.push_str(&text[copied..]);
+    Cow::Owned(output)
+}
+
+/// If this text, which starts with `<`, starts with a region removed by [`strip_unrendered`],
+/// returns the length of the region, including its closing delimiter.
+fn unrendered_region_len(text: &str) -> Option<usize> {
+    if let Some(contents) = text.strip_prefix("<!--") {
+        return Some(contents.find("-->").map_or(text.len(), |end| 4 + end + 3));
+    }
+
+    let name_len = text[1..]
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(text.len() - 1);
+    let name = &text[1..1 + name_len];
+    if !UNRENDERED_TAGS
+        .iter()
+        .any(|tag| tag.eq_ignore_ascii_case(name))
+    {
+        return None;
+    }
+    // The name may be followed by attributes, but must end there, unlike `<pre2>`.
+    if !text[1 + name_len..].starts_with(|c: char| c == '>' || c == '/' || c.is_whitespace()) {
+        return None;
+    }
+    let open_len = text.find('>')? + 1;
+    if text[..open_len - 1].ends_with('/') {
+        return None;
+    }
+    Some(
+        close_tag_end(&text[open_len..], name).map_or(text.len(), |close_end| open_len + close_end),
+    )
+}
+
+/// Finds the first closing tag `</name>` in this text, ignoring case and allowing whitespace before the `>`,
+/// and returns the offset of the end of the tag.
+fn close_tag_end(text: &str, name: &str) -> Option<usize> {
+    let mut search = 0;
+    while let Some(offset) = text[search..].find("</") {
+        let name_start = search + offset + 2;
+        let rest = &text[name_start..];
+        if rest
+            .get(..name.len())
+            .is_some_and(|candidate| candidate.eq_ignore_ascii_case(name))
+        {
+            let after_name = rest[name.len()..].trim_start();
+            if after_name.starts_with('>') {
+                return Some(text.len() - after_name.len() + 1);
+            }
+        }
+        search = name_start;
+    }
+    None
+}
+
+fn find_links_uncommented(text: &str) -> Vec<Wikilink<'_>> {
+    find_link_offsets(text)
+        .into_iter()
+        .map(|(_, link)| link)
+        .collect()
+}
+
+/// Finds each link in this wikitext, together with the byte offset at which it starts, in order of their starts.
+/// Links may be nested, as in the caption of `[[File:X.png|thumb|see [[Foo]] here]]`,
+/// so we match each `]]` with the most recent unmatched `[[`, and yield both the outer and the inner links.
+/// A `[[` that is never closed is ignored.
+fn find_link_offsets(text: &str) -> Vec<(usize, Wikilink<'_>)> {
+    let mut output = Vec::new();
+    let mut open = Vec::new();
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i + 1 < bytes.len() {
+        match &bytes[i..i + 2] {
+            b"[[" => {
+                open.push(i);
+                i += 2;
+            }
+            b"]]" => {
+                if let Some(start) = open.pop() {
+                    let contents = &text[start + 2..i];
+                    let (target, text) = contents.split_once('|').unwrap_or((contents, contents));
+                    output.push((
+                        start,
+                        Wikilink {
+                            target: Cow::Borrowed(target),
+                            text: Cow::Borrowed(text),
+                        },
+                    ));
+                }
+                i += 2;
+            }
+            _ => i += 1,
+        }
+    }
+    // Inner links are closed before the links containing them.
+    output.sort_by_key(|(start, _)| *start);
+    output
+}
+
+/// Parses the target of a redirect page, written as `#REDIRECT [[Target]]` at the very start of its wikitext.
+/// The `#REDIRECT` keyword is case-insensitive, and may be preceded by whitespace and followed by a colon.
+/// Returns [`None`] if the text does not start with a redirect, even if one is mentioned later on.
+pub fn parse_redirect(text: &str) -> Option<Wikilink<'_>> {
+    const KEYWORD: &str = "#redirect";
+    let text = text.trim_start();
+    let keyword = text.get(..KEYWORD.len())?;
+    if !keyword.eq_ignore_ascii_case(KEYWORD) {
+        return None;
+    }
+    let rest = text[KEYWORD.len()..].trim_start();
+    let rest = rest.strip_prefix(':').unwrap_or(rest).trim_start();
+    let rest = rest.strip_prefix("[[")?;
+    let contents = &rest[..rest.find("]]")?];
+    let (target, text) = contents.split_once('|').unwrap_or((contents, contents));
+    Some(Wikilink {
+        target: Cow::Borrowed(target),
+        text: Cow::Borrowed(text),
+    })
+}
+
+/// A section of an article, starting at a heading such as `== See also ==`.
+#[derive(Debug)]
+pub struct Section<'a> {
+    /// The text of the heading, with the surrounding `=` signs and whitespace removed.
+    pub name: &'a str,
+    /// The byte offset of the start of the heading's line.
+    pub start: usize,
+}
+
+/// Finds the headings in this wikitext, in order.
+/// A heading is a line that starts and ends with at least two `=` signs, such as `=== History ===`.
+/// Any text before the first heading is the lead, which has no heading.
+pub fn find_sections(text: &str) -> Vec<Section<'_>> {
     let mut output = Vec::new();
-    for (start, _) in text.match_indices("[[") {
-        if let Some(mut end) = text[start + 2..].find("]]") {
-            end += start + 2;
-            let contents = &text[start + 2..end];
-            match contents.split_once('|') {
-                Some((target, text)) => output.push(Wikilink {
-                    target: Cow::Borrowed(target),
-                    text: Cow::Borrowed(text),
-                }),
-                None => output.push(Wikilink {
-                    target: Cow::Borrowed(contents),
-                    text: Cow::Borrowed(contents),
-                }),
+    let mut start = 0;
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if trimmed.len() > 4 && trimmed.starts_with("==") && trimmed.ends_with("==") {
+            let name = trimmed.trim_matches('=').trim();
+            if !name.is_empty() {
+                output.push(Section { name, start });
             }
         }
+        start += line.len();
     }
     output
 }
 
+/// Finds a list of all links in this wikitext file, together with the name of the section each appears in,
+/// or [`None`] for links in the lead.
+/// As in [`find_links`], links inside HTML comments, `<nowiki>` blocks and `<pre>` blocks are ignored.
+pub fn find_links_by_section(text: &str) -> Vec<(Wikilink<'static>, Option<String>)> {
+    let text = strip_unrendered(text);
+    let sections = find_sections(&text);
+    find_link_offsets(&text)
+        .into_iter()
+        .map(|(offset, link)| {
+            // The section containing the link is the last one that starts before it.
+            let section = sections
+                .partition_point(|section| section.start <= offset)
+                .checked_sub(1)
+                .map(|i| sections[i].name.to_owned());
+            (link.to_owned(), section)
+        })
+        .collect()
+}
+
+/// Approximately counts the words in this wikitext file.
+/// Templates (`{{...}}`, possibly nested) are ignored, and any token containing no alphanumeric characters,
+/// such as the `==` of a heading, is not counted as a word.
+/// Link brackets are treated as whitespace, so both the target and the text of a piped link are counted.
+pub fn count_words(text: &str) -> u32 {
+    let mut words = 0;
+    let mut template_depth = 0u32;
+    let mut in_word = false;
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with("{{") {
+            template_depth += 1;
+            in_word = false;
+            rest = &rest[2..];
+            continue;
+        }
+        if rest.starts_with("}}") && template_depth > 0 {
+            template_depth -= 1;
+            rest = &rest[2..];
+            continue;
+        }
+        rest = &rest[c.len_utf8()..];
+        if template_depth > 0 {
+            continue;
+        }
+
+        if c.is_whitespace() || matches!(c, '[' | ']' | '|') {
+            in_word = false;
+        } else if c.is_alphanumeric() && !in_word {
+            in_word = true;
+            words += 1;
+        }
+    }
+    words
+}
+
 #[derive(Debug)]
 pub struct Wikilink<'a> {
     pub target: Cow<'a, str>,
@@ -46,6 +267,15 @@ impl<'a> Wikilink<'a> {
         }
     }
 
+    /// Gets the display text, with HTML entities decoded and surrounding whitespace trimmed,
+    /// as the text would be displayed to readers.
+    /// For example, `[[Foo|caf&eacute;]]` has normalised text `café`.
+    pub fn normalized_text(&self) -> String {
+        html_escape::decode_html_entities(&self.text)
+            .trim()
+            .to_owned()
+    }
+
     /// Gets the target, without any anchors indicated by `#`, then canonicalised.
     pub fn target_root(&self) -> String {
         match self.target.split_once('#') {
@@ -54,3 +284,118 @@ impl<'a> Wikilink<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn target_root_matches_canonicalise_wikilink() {
+        let cases = [
+            ("anarchism", "Anarchism"),
+            ("Anarchism#History", "Anarchism"),
+            (
+                "category:Political_philosophy",
+                "Category:Political philosophy",
+            ),
+            ("Category : Anarchism#Top", "Category:Anarchism"),
+            ("user:_jimbo_wales", "User:Jimbo wales"),
+            (
+                "Nineteen_Eighty-Four:_The_Musical",
+                "Nineteen Eighty-Four: The Musical",
+            ),
+            ("Caf%C3%A9#Menu", "Café"),
+        ];
+        for (target, expected) in cases {
+            let link = Wikilink {
+                target: target.into(),
+                text: Cow::Borrowed(""),
+            };
+            let root = target.split_once('#').map_or(target, |(root, _)| root);
+            assert_eq!(link.target_root(), canonicalise_wikilink(root), "{target}");
+            assert_eq!(link.target_root(), expected, "{target}");
+        }
+    }
+
+    /// The offset, target and text of each link found by [`find_link_offsets`].
+    fn link_offsets(text: &str) -> Vec<(usize, &str, &str)> {
+        find_link_offsets(text)
+            .into_iter()
+            .map(|(start, link)| match (link.target, link.text) {
+                (Cow::Borrowed(target), Cow::Borrowed(text)) => (start, target, text),
+                _ => unreachable!("links borrow from the wikitext"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn image_captions_may_contain_links() {
+        assert_eq!(
+            link_offsets("[[File:X|thumb|see [[Y]]]]"),
+            vec![(0, "File:X", "thumb|see [[Y]]"), (19, "Y", "Y")]
+        );
+        assert_eq!(
+            link_offsets("a [[File:X.png|thumb|see [[Foo]] here]] b"),
+            vec![
+                (2, "File:X.png", "thumb|see [[Foo]] here"),
+                (25, "Foo", "Foo")
+            ]
+        );
+    }
+
+    #[test]
+    fn links_may_be_nested_between_pipes() {
+        assert_eq!(
+            link_offsets("[[a|[[b]]|c]]"),
+            vec![(0, "a", "[[b]]|c"), (4, "b", "b")]
+        );
+    }
+
+    #[test]
+    fn unbalanced_brackets_are_ignored() {
+        assert_eq!(link_offsets("[[a [[b]]"), vec![(4, "b", "b")]);
+        assert_eq!(link_offsets("[[a]] ]] [["), vec![(0, "a", "a")]);
+        assert_eq!(link_offsets("]]x[[y"), vec![]);
+    }
+
+    /// The targets of the links found by [`find_links`].
+    fn targets(text: &str) -> Vec<String> {
+        find_links(text)
+            .into_iter()
+            .map(|link| link.target.into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn commented_out_links_are_ignored() {
+        assert_eq!(targets("a [[B]] <!-- [[C]] --> [[D]]"), vec!["B", "D"]);
+        assert_eq!(targets("<!--\n[[A]]\n-->[[B]]<!-- [[C]]"), vec!["B"]);
+    }
+
+    #[test]
+    fn unrendered_tags_may_have_attributes_and_any_case() {
+        assert_eq!(
+            strip_unrendered(
+                "a<pre class=\"x\">[[A]]</pre>b<nowiki >[[B]]</nowiki >c<NOWIKI>[[C]]</NoWiki>d<PRE>[[D]]</PRE \n>e"
+            ),
+            "abcde"
+        );
+        assert_eq!(targets("<Pre>[[A]]</pre>[[B]]<pre>[[C]]"), vec!["B"]);
+    }
+
+    #[test]
+    fn other_tags_are_left_alone() {
+        for text in [
+            "[[A]]",
+            "<nowiki/>[[A]]",
+            "<nowiki />[[A]]",
+            "<prefix>[[A]]</prefix>",
+            "<pre2>[[A]]</pre2>",
+            "<ref>[[A]]</ref>",
+            "1 < 2 [[A]]",
+            "[[A]] <pre",
+        ] {
+            assert!(matches!(strip_unrendered(text), Cow::Borrowed(_)), "{text}");
+        }
+    }
+}
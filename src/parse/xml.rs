@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+
 use nom::{
     bytes::complete::{tag, take_while, take_while1},
     IResult,
@@ -8,7 +10,10 @@ pub struct Element<'a> {
     pub name: &'a str,
     pub attributes: Vec<(&'a str, &'a str)>,
     pub children: Vec<Element<'a>>,
-    pub text: &'a str,
+    /// The text before the first child element, with character entities such as `&amp;` decoded.
+    /// The contents of any CDATA sections are included literally.
+    /// This only allocates if the text contains an entity or a CDATA section.
+    pub text: Cow<'a, str>,
 }
 
 pub fn shorten(text: String) -> String {
@@ -37,7 +42,7 @@ impl<'a> Element<'a> {
                 .iter()
                 .map(|child| child.name)
                 .collect::<Vec<_>>(),
-            shorten(self.text.to_owned()),
+            shorten(self.text.to_string()),
         )
     }
 
@@ -92,7 +97,7 @@ fn parse_attribute(input: &str) -> IResult<&str, (&str, &str)> {
 }
 
 /// Returns `true` if this element is auto-closed.
-fn parse_open_tag(input: &str) -> IResult<&str, (Element, bool)> {
+fn parse_open_tag(input: &str) -> IResult<&str, (Element<'_>, bool)> {
     let (input, _) = tag("<")(input)?;
     let (input, name) = take_while1(|c: char| c.is_ascii_alphanumeric())(input)?;
     let (mut input, ()) = parse_whitespace(input)?;
@@ -101,7 +106,7 @@ fn parse_open_tag(input: &str) -> IResult<&str, (Element, bool)> {
         name,
         attributes: Vec::new(),
         children: Vec::new(),
-        text: "",
+        text: Cow::Borrowed(""),
     };
 
     while input
@@ -130,7 +135,85 @@ fn parse_close_tag(input: &str) -> IResult<&str, &str> {
     Ok((input, name))
 }
 
-pub fn parse_element(input: &str) -> IResult<&str, Element> {
+/// Skips any comments `<!-- ... -->` and processing instructions `<? ... ?>`, together with whitespace around them.
+fn skip_comments(mut input: &str) -> IResult<&str, ()> {
+    loop {
+        let (new_input, ()) = parse_whitespace(input)?;
+        input = new_input;
+        let (start, end) = if input.starts_with("<!--") {
+            ("<!--", "-->")
+        } else if input.starts_with("<?") {
+            ("<?", "?>")
+        } else {
+            return Ok((input, ()));
+        };
+        match input[start.len()..].find(end) {
+            Some(i) => input = &input[start.len() + i + end.len()..],
+            None => {
+                return Err(nom::Err::Error(nom::error::Error::new(
+                    input,
+                    nom::error::ErrorKind::TakeUntil,
+                )))
+            }
+        }
+    }
+}
+
+/// The delimiters of a CDATA section, whose contents are literal text that may contain `<` and `&`.
+const CDATA_START: &str = "<![CDATA[";
+const CDATA_END: &str = "]]>";
+
+/// Splits a CDATA section from the start of the input into its contents and the remaining input,
+/// or returns [`None`] if the input does not start with a CDATA section.
+/// A `]]` that isn't followed by `>` does not end the section.
+fn parse_cdata(input: &str) -> Option<IResult<&str, &str>> {
+    let contents = input.strip_prefix(CDATA_START)?;
+    Some(match contents.find(CDATA_END) {
+        Some(end) => Ok((&contents[end + CDATA_END.len()..], &contents[..end])),
+        None => Err(nom::Err::Error(nom::error::Error::new(
+            input,
+            nom::error::ErrorKind::TakeUntil,
+        ))),
+    })
+}
+
+/// Parses text up to the next tag, with character entities decoded.
+/// The contents of any CDATA sections are included literally, without decoding.
+/// This only allocates if the text contains an entity or a CDATA section.
+fn parse_text(input: &str) -> IResult<&str, Cow<'_, str>> {
+    let (mut input, text) = take_while(|c: char| c != '<')(input)?;
+    let mut result = html_escape::decode_html_entities(text);
+    while let Some(cdata) = parse_cdata(input) {
+        let (new_input, contents) = cdata?;
+        let (new_input, text) = take_while(|c: char| c != '<')(new_input)?;
+        let result = result.to_mut();
+        result.push_str(contents);
+        result.push_str(&html_escape::decode_html_entities(text));
+        input = new_input;
+    }
+    Ok((input, result))
+}
+
+/// Skips any text, CDATA sections, comments and processing instructions, up to the next tag.
+fn skip_text_and_comments(mut input: &str) -> IResult<&str, ()> {
+    loop {
+        let (new_input, _) = take_while(|c: char| c != '<')(input)?;
+        let new_input = match parse_cdata(new_input) {
+            Some(cdata) => cdata?.0,
+            None => new_input,
+        };
+        let (new_input, ()) = skip_comments(new_input)?;
+        if new_input.len() == input.len() {
+            return Ok((new_input, ()));
+        }
+        input = new_input;
+    }
+}
+
+/// Parses an element, skipping any comments and processing instructions before it or between its children.
+/// Text after a comment is discarded, like any text after the element's first child.
+pub fn parse_element(input: &str) -> IResult<&str, Element<'_>> {
+    let (input, ()) = skip_comments(input)?;
     let (input, (mut element, auto_closed)) = parse_open_tag(input)?;
 
     if auto_closed {
@@ -141,15 +224,15 @@ pub fn parse_element(input: &str) -> IResult<&str, Element> {
         return Ok((input, element));
     }
 
-    let (input, text) = take_while(|c: char| c != '<')(input)?;
+    let (input, text) = parse_text(input)?;
     element.text = text;
 
-    let (mut input, ()) = parse_whitespace(input)?;
+    let (mut input, ()) = skip_text_and_comments(input)?;
 
     while !input.is_empty() && !input.starts_with("</") {
         let (new_input, new_element) = parse_element(input)?;
         // This discards any additional text blocks.
-        let (new_input, _) = take_while(|c: char| c != '<')(new_input)?;
+        let (new_input, ()) = skip_text_and_comments(new_input)?;
         element.children.push(new_element);
         input = new_input;
     }
@@ -162,3 +245,71 @@ pub fn parse_element(input: &str) -> IResult<&str, Element> {
         Ok((input, element))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comments_and_processing_instructions_are_skipped() {
+        let xml = r#"<?xml version="1.0"?>
+<!-- A page from the dump -->
+<page>
+  <title>Anarchism</title>
+  <!-- The namespace comes next -->
+  <ns>0</ns>
+  <?php echo "ignored"; ?>
+  <revision>
+    <id>100</id><!-- no parent -->
+    <text>See [[Albedo]].</text>
+  </revision>
+</page>"#;
+        let (rest, page) = parse_element(xml).unwrap();
+        assert_eq!(rest, "");
+        assert_eq!(page.name, "page");
+        assert_eq!(
+            page.children
+                .iter()
+                .map(|child| child.name)
+                .collect::<Vec<_>>(),
+            vec!["title", "ns", "revision"]
+        );
+        assert_eq!(page.find("title").unwrap().text, "Anarchism");
+        let revision = page.find("revision").unwrap();
+        assert_eq!(revision.find("id").unwrap().text, "100");
+        assert_eq!(revision.find("text").unwrap().text, "See [[Albedo]].");
+    }
+
+    #[test]
+    fn unterminated_comments_are_errors() {
+        assert!(parse_element("<page><!-- never closed <title>A</title></page>").is_err());
+    }
+
+    #[test]
+    fn cdata_is_literal_text() {
+        let (rest, text) =
+            parse_element("<text><![CDATA[if (a < b && c) { x &amp; y }]]></text>").unwrap();
+        assert_eq!(rest, "");
+        assert_eq!(text.text, "if (a < b && c) { x &amp; y }");
+        assert!(text.children.is_empty());
+    }
+
+    #[test]
+    fn cdata_only_ends_at_the_full_delimiter() {
+        let (_, text) = parse_element("<text><![CDATA[a[[b]] ]]c]]]></text>").unwrap();
+        assert_eq!(text.text, "a[[b]] ]]c]");
+    }
+
+    #[test]
+    fn several_cdata_sections_are_joined_with_the_text_between_them() {
+        let (_, text) =
+            parse_element("<text>one &lt; <![CDATA[<two>]]> &amp; <![CDATA[&three;]]> four</text>")
+                .unwrap();
+        assert_eq!(text.text, "one < <two> & &three; four");
+    }
+
+    #[test]
+    fn unterminated_cdata_is_an_error() {
+        assert!(parse_element("<text><![CDATA[never closed</text>").is_err());
+    }
+}
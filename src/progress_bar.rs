@@ -1,22 +1,317 @@
-use std::time::Duration;
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{Read, Write},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
+};
 
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use serde::Serialize;
 
-pub fn normal_progress_bar(len: u64) -> ProgressBar {
+/// Something that can report the progress of a task.
+pub trait Progress: Send + Sync {
+    fn inc(&self, delta: u64);
+    fn set_position(&self, position: u64);
+    fn position(&self) -> u64;
+    fn set_message(&self, message: Cow<'static, str>);
+    fn finish(&self);
+}
+
+/// Terminal progress, drawn by `indicatif`.
+impl Progress for ProgressBar {
+    fn inc(&self, delta: u64) {
+        ProgressBar::inc(self, delta)
+    }
+
+    fn set_position(&self, position: u64) {
+        ProgressBar::set_position(self, position)
+    }
+
+    fn position(&self) -> u64 {
+        ProgressBar::position(self)
+    }
+
+    fn set_message(&self, message: Cow<'static, str>) {
+        ProgressBar::set_message(self, message)
+    }
+
+    fn finish(&self) {
+        ProgressBar::finish(self)
+    }
+}
+
+/// Where JSON progress events are written, if enabled.
+static JSON_OUTPUT: OnceLock<Mutex<File>> = OnceLock::new();
+/// Whether to hide the terminal progress bars.
+static HIDE_PROGRESS_BARS: AtomicBool = AtomicBool::new(false);
+
+/// The minimum time between two JSON events for the same task, so that we don't flood the output.
+const JSON_EVENT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Emits progress as newline-delimited JSON events on the given file descriptor,
+/// in addition to any terminal progress bars.
+#[cfg(unix)]
+pub fn enable_json_progress(fd: i32) -> anyhow::Result<()> {
+    use std::os::fd::FromRawFd;
+
+    // SAFETY: The file descriptor is supplied by the user, who is responsible for keeping it open for writing.
+    // We take ownership of it for the rest of the program.
+    let file = unsafe { File::from_raw_fd(fd) };
+    JSON_OUTPUT
+        .set(Mutex::new(file))
+        .map_err(|_| anyhow::Error::msg("JSON progress was already enabled"))
+}
+
+#[cfg(not(unix))]
+pub fn enable_json_progress(_fd: i32) -> anyhow::Result<()> {
+    Err(anyhow::Error::msg(
+        "JSON progress events are only supported on Unix",
+    ))
+}
+
+/// Stops drawing progress bars on the terminal.
+/// This is useful if progress is reported as JSON instead.
+pub fn hide_progress_bars() {
+    HIDE_PROGRESS_BARS.store(true, Ordering::SeqCst);
+}
+
+#[derive(Serialize)]
+struct ProgressEvent<'a> {
+    stage: &'a str,
+    position: u64,
+    total: Option<u64>,
+    message: &'a str,
+    finished: bool,
+}
+
+/// Progress reported as newline-delimited JSON events.
+pub struct JsonProgress {
+    /// The first message set for this task, which identifies the task in each event.
+    stage: OnceLock<String>,
+    total: Option<u64>,
+    position: AtomicU64,
+    message: Mutex<String>,
+    last_event: Mutex<Instant>,
+}
+
+impl JsonProgress {
+    fn new(total: Option<u64>) -> Self {
+        Self {
+            stage: OnceLock::new(),
+            total,
+            position: AtomicU64::new(0),
+            message: Mutex::new(String::new()),
+            last_event: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Writes an event, unless `force` is false and we wrote one too recently.
+    fn emit(&self, force: bool) {
+        let Some(output) = JSON_OUTPUT.get() else {
+            return;
+        };
+        {
+            // If another thread is already emitting an event, there's no need to emit another.
+            let Ok(mut last_event) = (if force {
+                Ok(self.last_event.lock().unwrap())
+            } else {
+                self.last_event.try_lock()
+            }) else {
+                return;
+            };
+            if !force && last_event.elapsed() < JSON_EVENT_INTERVAL {
+                return;
+            }
+            *last_event = Instant::now();
+        }
+
+        let message = self.message.lock().unwrap();
+        let event = ProgressEvent {
+            stage: self.stage.get().map_or("", String::as_str),
+            position: self.position.load(Ordering::SeqCst),
+            total: self.total,
+            message: &message,
+            finished: force,
+        };
+        let mut output = output.lock().unwrap();
+        // Progress reporting is best-effort, so we ignore errors writing events.
+        if serde_json::to_writer(&mut *output, &event).is_ok() {
+            let _ = writeln!(output);
+            let _ = output.flush();
+        }
+    }
+}
+
+impl Progress for JsonProgress {
+    fn inc(&self, delta: u64) {
+        self.position.fetch_add(delta, Ordering::SeqCst);
+        self.emit(false);
+    }
+
+    fn set_position(&self, position: u64) {
+        self.position.store(position, Ordering::SeqCst);
+        self.emit(false);
+    }
+
+    fn position(&self) -> u64 {
+        self.position.load(Ordering::SeqCst)
+    }
+
+    fn set_message(&self, message: Cow<'static, str>) {
+        self.stage.get_or_init(|| message.clone().into_owned());
+        *self.message.lock().unwrap() = message.into_owned();
+        self.emit(false);
+    }
+
+    fn finish(&self) {
+        self.emit(true);
+    }
+}
+
+/// Reports progress both on the terminal and, if enabled, as JSON events.
+/// Cloning this gives another handle to the same task.
+#[derive(Clone)]
+pub struct ProgressHandle {
+    terminal: ProgressBar,
+    json: Option<Arc<JsonProgress>>,
+}
+
+impl ProgressHandle {
+    fn new(terminal: ProgressBar, total: Option<u64>) -> Self {
+        if HIDE_PROGRESS_BARS.load(Ordering::SeqCst) {
+            terminal.set_draw_target(ProgressDrawTarget::hidden());
+        }
+        terminal.enable_steady_tick(Duration::from_millis(100));
+        Self {
+            terminal,
+            json: JSON_OUTPUT
+                .get()
+                .map(|_| Arc::new(JsonProgress::new(total))),
+        }
+    }
+
+    fn sinks(&self) -> impl Iterator<Item = &dyn Progress> {
+        std::iter::once(&self.terminal as &dyn Progress)
+            .chain(self.json.as_deref().map(|json| json as &dyn Progress))
+    }
+
+    /// Sets the message.
+    /// The first message of a task also names its stage in JSON events.
+    pub fn with_message(self, message: impl Into<Cow<'static, str>>) -> Self {
+        self.set_message(message);
+        self
+    }
+
+    pub fn inc(&self, delta: u64) {
+        self.sinks().for_each(|sink| sink.inc(delta));
+    }
+
+    pub fn set_position(&self, position: u64) {
+        self.sinks().for_each(|sink| sink.set_position(position));
+    }
+
+    pub fn position(&self) -> u64 {
+        self.terminal.position()
+    }
+
+    pub fn set_message(&self, message: impl Into<Cow<'static, str>>) {
+        let message = message.into();
+        self.sinks()
+            .for_each(|sink| sink.set_message(message.clone()));
+    }
+
+    pub fn finish(&self) {
+        self.sinks().for_each(|sink| sink.finish());
+    }
+
+    pub fn finish_with_message(&self, message: impl Into<Cow<'static, str>>) {
+        self.set_message(message);
+        self.finish();
+    }
+
+    pub fn finish_and_clear(&self) {
+        if let Some(json) = &self.json {
+            json.finish();
+        }
+        self.terminal.finish_and_clear();
+    }
+
+    /// Hides the terminal progress bar while running `f`, so that it can print to the terminal.
+    pub fn suspend<T>(&self, f: impl FnOnce() -> T) -> T {
+        self.terminal.suspend(f)
+    }
+
+    /// Wraps a reader so that bytes read through it advance this progress bar.
+    pub fn wrap_read<R: Read>(&self, inner: R) -> ProgressRead<R> {
+        ProgressRead {
+            inner,
+            progress: self.clone(),
+        }
+    }
+
+    /// The underlying terminal progress bar, for use with other `indicatif` utilities.
+    pub fn terminal(&self) -> &ProgressBar {
+        &self.terminal
+    }
+}
+
+/// A reader that reports the number of bytes read through it to a progress bar.
+pub struct ProgressRead<R> {
+    inner: R,
+    progress: ProgressHandle,
+}
+
+impl<R: Read> Read for ProgressRead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let result = self.inner.read(buf)?;
+        self.progress.inc(result as u64);
+        Ok(result)
+    }
+}
+
+pub fn normal_progress_bar(len: u64) -> ProgressHandle {
     let progress = ProgressBar::new(len);
     progress.set_style(
         ProgressStyle::with_template("{spinner:.green} {msg} {pos:.bold.bright}/{len:.bold.bright} [{elapsed_precise}] ({eta_precise})")
             .unwrap(),
     );
-    progress.enable_steady_tick(Duration::from_millis(100));
-    progress
+    ProgressHandle::new(progress, Some(len))
 }
 
-pub fn file_progress_bar(len: u64) -> ProgressBar {
+pub fn file_progress_bar(len: u64) -> ProgressHandle {
     let file_progress = ProgressBar::new(len);
     file_progress.set_style(ProgressStyle::with_template("{spinner:.green} {msg} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} ({eta_precise})")
         .unwrap()
         .progress_chars("#>-"));
-    file_progress.enable_steady_tick(Duration::from_millis(100));
-    file_progress
+    ProgressHandle::new(file_progress, Some(len))
+}
+
+/// A progress bar that counts steps, such as files downloaded, with a wide message.
+pub fn step_progress_bar(len: u64) -> ProgressHandle {
+    let progress = ProgressBar::new(len);
+    progress.set_style(ProgressStyle::with_template("[{pos}/{len}] {wide_msg}").unwrap());
+    ProgressHandle::new(progress, Some(len))
+}
+
+/// A spinner for long-running tasks with no fixed length, showing a count and a rate.
+pub fn status_spinner() -> ProgressHandle {
+    let status = ProgressBar::new_spinner();
+    status.set_style(
+        ProgressStyle::with_template(
+            "{spinner:.green} {msg} {pos:.bold.bright} [{elapsed_precise}] ({per_sec})",
+        )
+        .unwrap(),
+    );
+    ProgressHandle::new(status, None)
+}
+
+/// A spinner that only shows a message.
+pub fn message_spinner() -> ProgressHandle {
+    let spinner = ProgressBar::new_spinner();
+    spinner.set_style(ProgressStyle::with_template("{spinner:.green} {wide_msg}").unwrap());
+    ProgressHandle::new(spinner, None)
 }
@@ -0,0 +1,137 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+/// The number of bits of precision we keep below the most significant bit of each value.
+/// Each power-of-two range is split into `2^MANTISSA_BITS` buckets, so quantiles are accurate
+/// to within a relative error of about `2^-MANTISSA_BITS`.
+const MANTISSA_BITS: u32 = 4;
+
+/// A log-bucketed histogram over `u64` values, used to compute approximate quantiles over
+/// very large streams of values without storing them all.
+///
+/// Small values (less than `2^(MANTISSA_BITS + 1)`) are stored exactly.
+/// The count, sum, minimum and maximum are always exact.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StreamingHistogram {
+    buckets: Vec<u64>,
+    count: u64,
+    sum: u128,
+    min: Option<u64>,
+    max: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistogramSummary {
+    pub count: u64,
+    pub mean: f64,
+    pub median: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+}
+
+impl Display for HistogramSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "count {}, mean {:.2}, median {}, p90 {}, p99 {}, max {}",
+            self.count, self.mean, self.median, self.p90, self.p99, self.max
+        )
+    }
+}
+
+/// Returns the index of the bucket that `value` falls into.
+fn bucket_index(value: u64) -> usize {
+    let exponent = 63 - (value | 1).leading_zeros();
+    if exponent <= MANTISSA_BITS {
+        value as usize
+    } else {
+        let shift = exponent - MANTISSA_BITS;
+        let mantissa = (value >> shift) as usize & ((1 << MANTISSA_BITS) - 1);
+        (((shift + 1) as usize) << MANTISSA_BITS) + mantissa
+    }
+}
+
+/// Returns the smallest value that falls into the given bucket.
+fn bucket_lower_bound(index: usize) -> u64 {
+    if index < 2 << MANTISSA_BITS {
+        index as u64
+    } else {
+        let shift = (index >> MANTISSA_BITS) as u32 - 1;
+        let mantissa = (index & ((1 << MANTISSA_BITS) - 1)) as u64;
+        ((1 << MANTISSA_BITS) | mantissa) << shift
+    }
+}
+
+impl StreamingHistogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, value: u64) {
+        let index = bucket_index(value);
+        if index >= self.buckets.len() {
+            self.buckets.resize(index + 1, 0);
+        }
+        self.buckets[index] += 1;
+        self.count += 1;
+        self.sum += value as u128;
+        self.min = Some(self.min.map_or(value, |min| min.min(value)));
+        self.max = Some(self.max.map_or(value, |max| max.max(value)));
+    }
+
+    /// Adds all of the values recorded in `other` to this histogram.
+    pub fn merge(&mut self, other: &Self) {
+        if other.buckets.len() > self.buckets.len() {
+            self.buckets.resize(other.buckets.len(), 0);
+        }
+        for (bucket, other_bucket) in self.buckets.iter_mut().zip(&other.buckets) {
+            *bucket += other_bucket;
+        }
+        self.count += other.count;
+        self.sum += other.sum;
+        self.min = self.min.into_iter().chain(other.min).min();
+        self.max = self.max.into_iter().chain(other.max).max();
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.sum as f64 / self.count as f64
+        }
+    }
+
+    /// Returns an approximation to the `q`th quantile, where `q` is between 0 and 1.
+    /// The result is always between the true minimum and maximum values.
+    pub fn quantile(&self, q: f64) -> u64 {
+        let (Some(min), Some(max)) = (self.min, self.max) else {
+            return 0;
+        };
+        let rank = ((q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, bucket) in self.buckets.iter().enumerate() {
+            seen += bucket;
+            if seen >= rank {
+                return bucket_lower_bound(index).clamp(min, max);
+            }
+        }
+        max
+    }
+
+    pub fn summary(&self) -> HistogramSummary {
+        HistogramSummary {
+            count: self.count,
+            mean: self.mean(),
+            median: self.quantile(0.5),
+            p90: self.quantile(0.9),
+            p99: self.quantile(0.99),
+            max: self.max.unwrap_or(0),
+        }
+    }
+}
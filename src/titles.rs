@@ -1,17 +1,34 @@
-use std::{fmt::Display, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    io::{IsTerminal, Write},
+    path::PathBuf,
+    sync::OnceLock,
+};
 
+use console::style;
+use crossbeam::channel::Receiver;
 use percent_encoding::percent_decode_str;
+use serde::{Deserialize, Serialize};
 
-use crate::hierarchical_map::HierarchicalMap;
+use crate::{
+    hierarchical_map::HierarchicalMap,
+    memoise::memoise,
+    page::{get_dump_status, read_namespaces},
+    progress_bar::normal_progress_bar,
+};
 
 pub fn generate_title_map(full: bool) -> anyhow::Result<TitleMap> {
     let id_to_title = TitleMap::default();
     if !id_to_title.deserialise(full)? {
         // If we haven't already saved the title map to disk, we need to compute it in its entirety, then save it to disk.
-        let rx =
-            crate::page::page_stream(u64::MAX, 1, "Precomputing page IDs".to_owned(), |page| {
-                (page.id, page.title.to_owned())
-            })?;
+        let rx = crate::page::page_stream(
+            crate::page::StopCondition::ALL,
+            1,
+            false,
+            "Precomputing page IDs".to_owned(),
+            |page| (page.id, page.title.into_owned()),
+        )?;
 
         while let Ok((id, title)) = rx.recv() {
             id_to_title.insert(id, canonicalise_wikilink(&title));
@@ -25,6 +42,46 @@ pub fn generate_title_map(full: bool) -> anyhow::Result<TitleMap> {
     Ok(id_to_title)
 }
 
+/// Titles starting with these prefixes are lists of articles, such as `List of sovereign states`,
+/// which link to so many articles that they act like hubs.
+const LIST_ARTICLE_PREFIXES: [&str; 3] = ["List of ", "Index of ", "Timeline of "];
+
+/// Returns the IDs of the list articles in the main namespace, identified by their title prefixes.
+pub fn generate_list_articles() -> anyhow::Result<HashSet<u32>> {
+    generate_title_set("list_articles", "Finding list articles", |title| {
+        split_namespace(title).0.is_none()
+            && LIST_ARTICLE_PREFIXES
+                .iter()
+                .any(|prefix| title.starts_with(prefix))
+    })
+}
+
+/// Returns the IDs of the pages outside the main namespace, such as categories and portals.
+pub fn generate_non_articles() -> anyhow::Result<HashSet<u32>> {
+    generate_title_set("non_articles", "Finding non-article pages", |title| {
+        split_namespace(title).0.is_some()
+    })
+}
+
+/// Returns the IDs of the pages whose titles satisfy the predicate, caching the result under the given key.
+fn generate_title_set(
+    key: &str,
+    message: &str,
+    predicate: impl Fn(&str) -> bool,
+) -> anyhow::Result<HashSet<u32>> {
+    memoise(key, message, false, || {
+        let title_map = generate_title_map(true)?;
+        let rx = title_map.with_all_titles(message.to_owned());
+        let mut ids = HashSet::new();
+        while let Ok((id, title)) = rx.recv() {
+            if predicate(&title) {
+                ids.insert(id);
+            }
+        }
+        Ok(ids)
+    })
+}
+
 #[derive(Debug, Clone)]
 pub struct TitleMap {
     id_to_title: HierarchicalMap<u8, u32, String>,
@@ -34,10 +91,12 @@ pub struct TitleMap {
 impl Default for TitleMap {
     fn default() -> Self {
         Self {
-            id_to_title: HierarchicalMap::new(PathBuf::from("id_to_title"), id_short_key),
+            id_to_title: HierarchicalMap::new(PathBuf::from("id_to_title"), id_short_key)
+                .with_load_progress("Loading ID to title map"),
             title_to_id: HierarchicalMap::new(PathBuf::from("title_to_id"), |string: &String| {
                 title_short_key(string)
-            }),
+            })
+            .with_load_progress("Loading title to ID map"),
         }
     }
 }
@@ -97,11 +156,280 @@ impl TitleMap {
         self.id_to_title.with(&id, String::clone)
     }
 
+    /// Looks up the page with exactly this title.
+    /// Titles are stored with their namespace prefix, so a title with no namespace,
+    /// such as `Foo`, only matches a page in the main namespace and never `Category:Foo`.
     pub fn get_id(&self, title: &str) -> Option<u32> {
         self.title_to_id
             .with(&canonicalise_wikilink(title), u32::clone)
     }
 
+    /// Checks that the two halves of the title map agree with each other:
+    /// every title in `id_to_title` must map back to its ID in `title_to_id`, and vice versa.
+    /// Both halves must be fully loaded.
+    pub fn verify(&self) -> anyhow::Result<Vec<Discrepancy>> {
+        if !self.id_to_title.is_fully_loaded() || !self.title_to_id.is_fully_loaded() {
+            return Err(anyhow::Error::msg(
+                "the title map must be fully loaded to verify it",
+            ));
+        }
+
+        let mut discrepancies = Vec::new();
+        let rx = self
+            .id_to_title
+            .with_all("Checking IDs".to_owned(), |id, title| (*id, title.clone()));
+        while let Ok((id, title)) = rx.recv() {
+            match self.title_to_id.with(&title, u32::clone) {
+                None => discrepancies.push(Discrepancy::MissingTitle { id, title }),
+                Some(other_id) if other_id != id => discrepancies.push(Discrepancy::WrongId {
+                    title,
+                    id,
+                    other_id,
+                }),
+                Some(_) => {}
+            }
+        }
+
+        let rx = self
+            .title_to_id
+            .with_all("Checking titles".to_owned(), |title, id| {
+                (title.clone(), *id)
+            });
+        while let Ok((title, id)) = rx.recv() {
+            match self.id_to_title.with(&id, String::clone) {
+                None => discrepancies.push(Discrepancy::MissingId { title, id }),
+                Some(other_title) if other_title != title => {
+                    discrepancies.push(Discrepancy::WrongTitle {
+                        id,
+                        title,
+                        other_title,
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+
+        Ok(discrepancies)
+    }
+
+    /// Rebuilds `title_to_id` from `id_to_title`, which we treat as authoritative, and saves it to disk.
+    /// If several IDs have the same title, only one of them can be kept.
+    /// Both halves must be fully loaded.
+    pub fn repair(&self) -> anyhow::Result<()> {
+        if !self.id_to_title.is_fully_loaded() || !self.title_to_id.is_fully_loaded() {
+            return Err(anyhow::Error::msg(
+                "the title map must be fully loaded to repair it",
+            ));
+        }
+
+        self.title_to_id.get_map().write().unwrap().clear();
+        let rx = self
+            .id_to_title
+            .with_all("Rebuilding title map".to_owned(), |id, title| {
+                (*id, title.clone())
+            });
+        while let Ok((id, title)) = rx.recv() {
+            self.title_to_id.insert(title, id);
+        }
+        self.title_to_id.serialize()
+    }
+
+    /// Looks up many titles at once, returning their IDs in the same order.
+    /// Rather than binary searching the title map on disk once per title,
+    /// we group the titles by short key and read each bucket that we need exactly once.
+    /// If the map isn't fully loaded, the buckets are streamed from disk rather than loaded,
+    /// so a large batch doesn't leave the whole title map in memory.
+    pub fn get_ids(&self, titles: &[&str]) -> anyhow::Result<Vec<Option<u32>>> {
+        let titles = titles
+            .iter()
+            .map(|title| canonicalise_wikilink(title))
+            .collect::<Vec<_>>();
+        let mut by_short_key = HashMap::<String, HashMap<&str, Vec<usize>>>::new();
+        for (i, title) in titles.iter().enumerate() {
+            by_short_key
+                .entry(title_short_key(title))
+                .or_default()
+                .entry(title)
+                .or_default()
+                .push(i);
+        }
+
+        let progress_bar =
+            normal_progress_bar(titles.len() as u64).with_message("Looking up titles");
+        let mut ids = vec![None; titles.len()];
+        for (short_key, indices) in by_short_key {
+            if self.title_to_id.is_fully_loaded() {
+                let outer_map = self.title_to_id.get_map().read().unwrap();
+                if let Some(inner_map) = outer_map.get(&short_key) {
+                    let inner_map = inner_map.read().unwrap();
+                    for (title, indices) in &indices {
+                        for &i in indices {
+                            ids[i] = inner_map.get(*title).copied();
+                        }
+                    }
+                }
+            } else if self
+                .title_to_id
+                .get_map()
+                .read()
+                .unwrap()
+                .contains_key(&short_key)
+            {
+                self.title_to_id.for_each_in_bucket_file(
+                    &short_key,
+                    |title: String, id: u32| {
+                        if let Some(indices) = indices.get(title.as_str()) {
+                            for &i in indices {
+                                ids[i] = Some(id);
+                            }
+                        }
+                        Ok(())
+                    },
+                )?;
+            }
+            progress_bar.inc(indices.values().map(Vec::len).sum::<usize>() as u64);
+        }
+        progress_bar.finish();
+        Ok(ids)
+    }
+
+    /// Looks up the page with the given title in the namespace with the given numeric ID,
+    /// where the title does not include the namespace prefix, as in the SQL dumps.
+    /// Returns `None` if the title would be interpreted as belonging to a different namespace,
+    /// such as `Category:Foo` in the main namespace.
+    pub fn get_id_in_namespace(&self, namespace: i32, title: &str) -> Option<u32> {
+        let namespace = namespace_name(namespace)?;
+        let title = if namespace.is_empty() {
+            canonicalise_wikilink(title)
+        } else {
+            canonicalise_wikilink(&format!("{namespace}:{title}"))
+        };
+        if split_namespace(&title).0.unwrap_or_default() != namespace {
+            return None;
+        }
+        self.title_to_id.with(&title, u32::clone)
+    }
+
+    /// Looks up the given title, falling back to a case-insensitive search if there is no exact match.
+    /// Since short keys are case-insensitive, we only need to search the bucket for this title's short key.
+    /// Returns the canonical title that was matched, together with its ID.
+    pub fn get_id_ignore_case(&self, title: &str) -> anyhow::Result<Option<(String, u32)>> {
+        let title = canonicalise_wikilink(title);
+        if let Some(id) = self.title_to_id.with(&title, u32::clone) {
+            return Ok(Some((title, id)));
+        }
+
+        let lowercase_title = title.to_lowercase();
+        Ok(self
+            .scan_bucket(&title, |candidate| {
+                candidate.to_lowercase() == lowercase_title
+            })?
+            .into_iter()
+            .next())
+    }
+
+    /// Returns the IDs of every page whose title, ignoring its namespace, matches the given title.
+    /// For example, `Mercury` may match both `Mercury` and `Category:Mercury`.
+    /// If the given title has a namespace, it is only matched against titles in that namespace.
+    pub fn get_all_ids(&self, title: &str) -> anyhow::Result<Vec<u32>> {
+        let title = canonicalise_wikilink(title);
+        if split_namespace(&title).0.is_some() {
+            return Ok(self.get_id(&title).into_iter().collect());
+        }
+        Ok(self
+            .scan_bucket(&title, |candidate| split_namespace(candidate).1 == title)?
+            .into_iter()
+            .map(|(_, id)| id)
+            .collect())
+    }
+
+    /// Returns all `(title, id)` pairs in the bucket that `title` belongs to that satisfy the predicate.
+    /// Since short keys ignore case and namespaces, this bucket contains every title that matches
+    /// `title` up to case and namespace.
+    fn scan_bucket(
+        &self,
+        title: &str,
+        predicate: impl Fn(&str) -> bool,
+    ) -> anyhow::Result<Vec<(String, u32)>> {
+        let short_key = title_short_key(title);
+        if !self.title_to_id.is_fully_loaded() {
+            self.title_to_id.deserialize_bucket(short_key.clone())?;
+        }
+        let outer_map = self.title_to_id.get_map().read().unwrap();
+        Ok(match outer_map.get(&short_key) {
+            Some(inner_map) => inner_map
+                .read()
+                .unwrap()
+                .iter()
+                .filter(|(candidate, _)| predicate(candidate))
+                .map(|(candidate, id)| (candidate.clone(), *id))
+                .collect(),
+            None => Vec::new(),
+        })
+    }
+
+    /// Resolves an article title given by the user to an ID, raising an error if it could not be found.
+    /// If `ignore_case` is true and there was no exact match, we try a case-insensitive match,
+    /// and report the title that we found.
+    ///
+    /// If the title has no namespace and matches pages in several namespaces,
+    /// we ask the user to pick one, as long as we're running interactively.
+    pub fn resolve(&self, title: &str, ignore_case: bool) -> anyhow::Result<u32> {
+        if std::io::stdin().is_terminal() {
+            let candidates = self.get_all_ids(title)?;
+            if candidates.len() > 1 {
+                return self.choose_candidate(title, &candidates);
+            }
+        }
+
+        if ignore_case {
+            if let Some((canonical_title, id)) = self.get_id_ignore_case(title)? {
+                if canonical_title != canonicalise_wikilink(title) {
+                    println!(
+                        "Resolved {} to {}",
+                        style(title).bold(),
+                        style(&canonical_title).bold().bright()
+                    );
+                }
+                return Ok(id);
+            }
+        } else if let Some(id) = self.get_id(title) {
+            return Ok(id);
+        }
+        Err(anyhow::Error::msg(format!("article {title} not found")))
+    }
+
+    /// Asks the user to pick one of several pages matching the given title.
+    fn choose_candidate(&self, title: &str, candidates: &[u32]) -> anyhow::Result<u32> {
+        println!("{} is ambiguous:", style(title).bold());
+        for (i, id) in candidates.iter().enumerate() {
+            println!(
+                "{:>5} {}",
+                style(format!("{}.", i + 1)).dim(),
+                self.get_title(*id).unwrap_or_else(|| format!("<id {id}>"))
+            );
+        }
+        print!("Choose an article [1-{}]: ", candidates.len());
+        std::io::stdout().flush()?;
+
+        let mut choice = String::new();
+        std::io::stdin().read_line(&mut choice)?;
+        match choice.trim().parse::<usize>() {
+            Ok(choice) if (1..=candidates.len()).contains(&choice) => Ok(candidates[choice - 1]),
+            _ => Err(anyhow::Error::msg(format!(
+                "invalid choice {}",
+                choice.trim()
+            ))),
+        }
+    }
+
+    /// Streams every `(id, title)` pair in the title map.
+    /// The title map must be fully loaded.
+    pub fn with_all_titles(&self, message: String) -> Receiver<(u32, String)> {
+        self.id_to_title
+            .with_all(message, |id, title| (*id, title.clone()))
+    }
+
     fn mark_loaded(&self) {
         self.id_to_title.mark_loaded();
         self.title_to_id.mark_loaded();
@@ -124,7 +452,177 @@ impl TitleMap {
     }
 }
 
+/// A way in which the two halves of a [`TitleMap`] disagree.
+#[derive(Debug, Clone)]
+pub enum Discrepancy {
+    /// The ID has this title, but the title has no ID.
+    MissingTitle { id: u32, title: String },
+    /// The ID has this title, but the title has a different ID,
+    /// for instance because two pages have titles that canonicalise to the same title.
+    WrongId {
+        title: String,
+        id: u32,
+        other_id: u32,
+    },
+    /// The title has this ID, but the ID has no title.
+    MissingId { title: String, id: u32 },
+    /// The title has this ID, but the ID has a different title.
+    WrongTitle {
+        id: u32,
+        title: String,
+        other_title: String,
+    },
+}
+
+impl Display for Discrepancy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Discrepancy::MissingTitle { id, title } => {
+                write!(f, "id {id} has title {title}, which has no id")
+            }
+            Discrepancy::WrongId {
+                title,
+                id,
+                other_id,
+            } => write!(f, "id {id} has title {title}, which has id {other_id}"),
+            Discrepancy::MissingId { title, id } => {
+                write!(f, "title {title} has id {id}, which has no title")
+            }
+            Discrepancy::WrongTitle {
+                id,
+                title,
+                other_title,
+            } => write!(
+                f,
+                "title {title} has id {id}, which has title {other_title}"
+            ),
+        }
+    }
+}
+
+/// A namespace of the wiki, as listed in the `<siteinfo>` block of the articles dump.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Namespace {
+    pub id: i32,
+    /// The canonical name of this namespace, which is empty for the main namespace.
+    pub name: String,
+    /// Whether the first letter of titles in this namespace is automatically capitalised.
+    pub first_letter: bool,
+}
+
+/// The namespaces of the wiki, used to canonicalise and split titles.
+struct NamespaceTable {
+    namespaces: Vec<Namespace>,
+    /// Maps lowercase namespace names and aliases to indices into `namespaces`.
+    names: HashMap<String, usize>,
+}
+
+/// Aliases that MediaWiki accepts for some namespaces, given as the alias and the namespace ID.
+/// These are not listed in the `<siteinfo>` block.
+///
+/// <https://en.wikipedia.org/wiki/Wikipedia:Namespace#Aliases>
+const NAMESPACE_ALIASES: [(&str, i32); 6] = [
+    ("image", 6),
+    ("image talk", 7),
+    ("project", 4),
+    ("project talk", 5),
+    ("wp", 4),
+    ("wt", 5),
+];
+
+impl NamespaceTable {
+    fn new(namespaces: Vec<Namespace>) -> Self {
+        let mut names = HashMap::new();
+        for (i, namespace) in namespaces.iter().enumerate() {
+            if !namespace.name.is_empty() {
+                names.insert(namespace.name.to_lowercase(), i);
+            }
+        }
+        for (alias, id) in NAMESPACE_ALIASES {
+            if let Some(i) = namespaces.iter().position(|namespace| namespace.id == id) {
+                names.entry(alias.to_owned()).or_insert(i);
+            }
+        }
+        Self { namespaces, names }
+    }
+
+    /// The namespaces of English Wikipedia, used if the articles dump is not available.
+    ///
+    /// <https://en.wikipedia.org/wiki/Wikipedia:Namespace>
+    fn english_wikipedia() -> Self {
+        let namespaces = [
+            (-2, "Media"),
+            (-1, "Special"),
+            (0, ""),
+            (2, "User"),
+            (4, "Wikipedia"),
+            (6, "File"),
+            (8, "MediaWiki"),
+            (10, "Template"),
+            (12, "Help"),
+            (14, "Category"),
+            (100, "Portal"),
+            (118, "Draft"),
+            (710, "TimedText"),
+            (828, "Module"),
+        ];
+        Self::new(
+            namespaces
+                .into_iter()
+                .map(|(id, name)| Namespace {
+                    id,
+                    name: name.to_owned(),
+                    first_letter: true,
+                })
+                .collect(),
+        )
+    }
+
+    /// Finds the namespace with the given name or alias, ignoring case and treating underscores as spaces.
+    fn get(&self, name: &str) -> Option<&Namespace> {
+        let name = name.trim().replace('_', " ").to_lowercase();
+        self.names.get(&name).map(|i| &self.namespaces[*i])
+    }
+
+    fn get_by_id(&self, id: i32) -> Option<&Namespace> {
+        self.namespaces.iter().find(|namespace| namespace.id == id)
+    }
+}
+
+/// The namespaces of the wiki, set by [`load_namespaces`].
+static NAMESPACES: OnceLock<NamespaceTable> = OnceLock::new();
+
+/// Reads the namespaces of the wiki from the articles dump, for use in canonicalising and splitting titles.
+/// This must be called before any title is canonicalised.
+/// If the namespaces can't be read, for instance because the dump has not been downloaded,
+/// we warn and fall back to the namespaces of English Wikipedia.
+pub fn load_namespaces() -> anyhow::Result<()> {
+    let table = match get_dump_status().and_then(|dump_status| read_namespaces(&dump_status)) {
+        Ok(namespaces) => NamespaceTable::new(namespaces),
+        Err(err) => {
+            eprintln!(
+                "{} could not read the namespaces from the articles dump, so using those of English Wikipedia: {err}",
+                style("warning:").yellow().bold()
+            );
+            NamespaceTable::english_wikipedia()
+        }
+    };
+    NAMESPACES
+        .set(table)
+        .map_err(|_| anyhow::Error::msg("the namespaces were already loaded"))
+}
+
+/// Returns the namespace table.
+/// If [`load_namespaces`] was never called, such as in tests, these are the namespaces of English Wikipedia.
+fn namespaces() -> &'static NamespaceTable {
+    NAMESPACES.get_or_init(NamespaceTable::english_wikipedia)
+}
+
 /// <https://en.wikipedia.org/wiki/Help:Link#Conversion_to_canonical_form>
+///
+/// An empty input, such as the target of `[[|text]]`, canonicalises to the empty string.
+/// As in [`split_namespace`], a colon only introduces a namespace if the text before it is a known namespace,
+/// so `c: drive` canonicalises to the main namespace article `C: drive`.
 pub fn canonicalise_wikilink(input: &str) -> String {
     let input = match String::from_utf8(percent_decode_str(input).collect::<Vec<_>>()) {
         Ok(string) => string,
@@ -135,40 +633,31 @@ pub fn canonicalise_wikilink(input: &str) -> String {
     };
     let input = html_escape::decode_html_entities(&input);
 
+    let namespaces = namespaces();
     let (namespace, input) = match input.split_once(':') {
-        Some((namespace, remaining_input)) => {
-            let namespace = match namespace.trim().to_lowercase().as_str() {
-                "main" => Some("Main"),
-                "article" => Some("Article"),
-                "user" => Some("User"),
-                "wikipedia" => Some("Wikipedia"),
-                "file" => Some("File"),
-                "mediawiki" => Some("MediaWiki"),
-                "template" => Some("Template"),
-                "help" => Some("Help"),
-                "category" => Some("Category"),
-                "portal" => Some("Portal"),
-                "draft" => Some("Draft"),
-                "timedtext" => Some("TimedText"),
-                "module" => Some("Module"),
-                "special" => Some("Special"),
-                "media" => Some("Media"),
-                _ => None,
-            };
-            match namespace {
-                Some(namespace) => (Some(namespace), remaining_input),
-                None => (None, input.as_ref()),
-            }
-        }
+        Some((namespace, remaining_input)) => match namespaces.get(namespace) {
+            // Like the namespace itself, the remainder may be separated from the colon by spaces or underscores,
+            // as in `User: Jimbo`, which must be removed before capitalising its first letter.
+            Some(namespace) => (
+                Some(namespace),
+                remaining_input.trim_start_matches([' ', '_']),
+            ),
+            None => (None, input.as_ref()),
+        },
         None => (None, input.as_ref()),
     };
 
+    // The first-letter rule applies to the title after the namespace, not to the namespace prefix,
+    // which is replaced by its canonical name below.
+    let first_letter = namespace
+        .or_else(|| namespaces.get_by_id(0))
+        .is_none_or(|namespace| namespace.first_letter);
     let input = match input.chars().next() {
-        Some(first_letter) => first_letter
+        Some(first_letter_char) if first_letter => first_letter_char
             .to_uppercase()
             .chain(input.chars().skip(1))
             .collect::<String>(),
-        None => input.to_owned(),
+        _ => input.to_owned(),
     };
 
     let input = input
@@ -178,45 +667,49 @@ pub fn canonicalise_wikilink(input: &str) -> String {
         .join(" ");
 
     match namespace {
-        Some(namespace) => format!("{namespace}:{input}"),
+        Some(namespace) => format!("{}:{input}", namespace.name),
         None => input,
     }
 }
 
+/// Parses an article title given on the command line, rejecting titles that are empty or only whitespace,
+/// since they canonicalise to a title that no article has.
+pub fn parse_article_title(title: &str) -> Result<String, String> {
+    if title.trim().is_empty() {
+        Err("article title cannot be empty".to_owned())
+    } else {
+        Ok(title.to_owned())
+    }
+}
+
 /// Splits this title into a namespace and the remainder.
 ///
+/// Many article titles contain colons, such as `Nineteen Eighty-Four: The Musical` or `C: drive`.
+/// The text before the first colon is only treated as a namespace if it is a known namespace name or alias,
+/// so such titles are returned whole, with no namespace.
+///
 /// https://en.wikipedia.org/wiki/Help:Link
 pub fn split_namespace(title: &str) -> (Option<&'static str>, &str) {
     let title = title.strip_prefix(':').unwrap_or(title);
     match title.split_once(':') {
-        Some((namespace, remainder)) => {
-            let namespace = match namespace.trim().to_lowercase().as_str() {
-                "main" => Some("Main"),
-                "article" => Some("Article"),
-                "user" => Some("User"),
-                "wikipedia" => Some("Wikipedia"),
-                "file" => Some("File"),
-                "mediawiki" => Some("MediaWiki"),
-                "template" => Some("Template"),
-                "help" => Some("Help"),
-                "category" => Some("Category"),
-                "portal" => Some("Portal"),
-                "draft" => Some("Draft"),
-                "timedtext" => Some("TimedText"),
-                "module" => Some("Module"),
-                "special" => Some("Special"),
-                "media" => Some("Media"),
-                _ => None,
-            };
-            match namespace {
-                Some(namespace) => (Some(namespace), remainder),
-                None => (None, title),
-            }
-        }
+        Some((namespace, remainder)) => match namespaces().get(namespace) {
+            Some(namespace) => (Some(namespace.name.as_str()), remainder),
+            None => (None, title),
+        },
         None => (None, title),
     }
 }
 
+/// Returns the canonical name of the namespace with the given numeric ID, as used in the SQL dumps.
+/// The main namespace has ID 0 and no name, so this returns `Some("")`.
+///
+/// https://en.wikipedia.org/wiki/Wikipedia:Namespace
+pub fn namespace_name(id: i32) -> Option<&'static str> {
+    namespaces()
+        .get_by_id(id)
+        .map(|namespace| namespace.name.as_str())
+}
+
 pub fn is_interwiki_link(title: &str) -> bool {
     let prefixes = ["wikibooks"];
 
@@ -225,3 +718,200 @@ pub fn is_interwiki_link(title: &str) -> bool {
         .iter()
         .any(|prefix| title.starts_with(&format!("{prefix}:")))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_storage::MemoryStorage;
+    use std::sync::Arc;
+
+    #[test]
+    fn split_namespace_finds_known_namespaces() {
+        assert_eq!(
+            split_namespace("Category:Physics"),
+            (Some("Category"), "Physics")
+        );
+        assert_eq!(
+            split_namespace(":Category:Physics"),
+            (Some("Category"), "Physics")
+        );
+        assert_eq!(split_namespace("Physics"), (None, "Physics"));
+        // Aliases and different cases are split into the canonical namespace name.
+        assert_eq!(split_namespace("Image:Foo.png"), (Some("File"), "Foo.png"));
+        assert_eq!(split_namespace("WP:NPOV"), (Some("Wikipedia"), "NPOV"));
+        assert_eq!(
+            split_namespace("category:Physics"),
+            (Some("Category"), "Physics")
+        );
+    }
+
+    #[test]
+    fn colons_outside_namespaces_stay_in_the_title() {
+        for title in [
+            "Nineteen Eighty-Four: The Musical",
+            "C: drive",
+            "Star Wars: Episode IV – A New Hope",
+            "Re:Zero",
+        ] {
+            assert_eq!(split_namespace(title), (None, title));
+            assert_eq!(canonicalise_wikilink(title), title);
+        }
+        assert_eq!(canonicalise_wikilink("c:_drive"), "C: drive");
+        assert_eq!(canonicalise_wikilink("re:Zero"), "Re:Zero");
+    }
+
+    #[test]
+    fn canonicalise_wikilink_uses_namespace_table() {
+        assert_eq!(
+            canonicalise_wikilink("category:physics"),
+            "Category:Physics"
+        );
+        assert_eq!(canonicalise_wikilink("image:foo.png"), "File:Foo.png");
+        assert_eq!(canonicalise_wikilink("Project:About"), "Wikipedia:About");
+        assert_eq!(canonicalise_wikilink("foo_bar"), "Foo bar");
+    }
+
+    #[test]
+    fn first_letter_rule_applies_after_the_namespace() {
+        assert_eq!(canonicalise_wikilink("user:jimbo"), "User:Jimbo");
+        assert_eq!(canonicalise_wikilink("User: jimbo"), "User:Jimbo");
+        assert_eq!(
+            canonicalise_wikilink("user:_jimbo_wales"),
+            "User:Jimbo wales"
+        );
+        assert_eq!(canonicalise_wikilink("category:écoles"), "Category:Écoles");
+        // The rest of the title keeps its case.
+        assert_eq!(
+            canonicalise_wikilink("template:iNFOBOX"),
+            "Template:INFOBOX"
+        );
+    }
+
+    #[test]
+    fn case_sensitive_namespaces_keep_their_first_letter() {
+        let table = NamespaceTable::new(vec![
+            Namespace {
+                id: 0,
+                name: String::new(),
+                first_letter: true,
+            },
+            Namespace {
+                id: 828,
+                name: "Module".to_owned(),
+                first_letter: false,
+            },
+        ]);
+        let module = table.get("module").unwrap();
+        assert_eq!(module.name, "Module");
+        assert!(!module.first_letter);
+        assert!(table.get_by_id(0).unwrap().first_letter);
+        assert!(table.get("Category").is_none());
+    }
+
+    #[test]
+    fn decoded_multibyte_first_letters_are_capitalised() {
+        assert_eq!(canonicalise_wikilink("Caf%C3%A9"), "Café");
+        assert_eq!(canonicalise_wikilink("%C3%A9cole_normale"), "École normale");
+        assert_eq!(canonicalise_wikilink("&eacute;t&eacute;"), "Été");
+        assert_eq!(canonicalise_wikilink("&#x3b1;lpha"), "Αlpha");
+        assert_eq!(canonicalise_wikilink("%CE%B1%CE%B2"), "Αβ");
+        assert_eq!(
+            canonicalise_wikilink("category:%C3%A9coles"),
+            "Category:Écoles"
+        );
+    }
+
+    /// A title map stored in the given storage.
+    fn memory_title_map(storage: &MemoryStorage) -> TitleMap {
+        let map = TitleMap::default();
+        TitleMap {
+            id_to_title: map.id_to_title.with_storage(Arc::new(storage.clone())),
+            title_to_id: map.title_to_id.with_storage(Arc::new(storage.clone())),
+        }
+    }
+
+    #[test]
+    fn batched_lookups_match_single_lookups() {
+        let storage = MemoryStorage::default();
+        let map = memory_title_map(&storage);
+        for (id, title) in [
+            (12, "Anarchism"),
+            (39, "Albedo"),
+            (290, "A"),
+            (303, "Alabama"),
+            (690, "Category:Anarchism"),
+            (1000, "Écoles"),
+        ] {
+            map.insert(id, title.to_owned());
+        }
+        map.mark_loaded();
+        map.serialise().unwrap();
+
+        let titles = [
+            "anarchism",
+            "Albedo",
+            "Missing",
+            "a",
+            "category:Anarchism",
+            "Anarchism",
+            "%C3%89coles",
+            "Al",
+        ];
+        let expected = vec![
+            Some(12),
+            Some(39),
+            None,
+            Some(290),
+            Some(690),
+            Some(12),
+            Some(1000),
+            None,
+        ];
+        assert_eq!(map.get_ids(&titles).unwrap(), expected);
+
+        let lazy_map = memory_title_map(&storage);
+        assert!(lazy_map.deserialise(false).unwrap());
+        assert_eq!(lazy_map.get_ids(&titles).unwrap(), expected);
+        // The buckets were streamed, not loaded.
+        assert_eq!(lazy_map.title_to_id.total_keys(), 0);
+        let single = titles
+            .iter()
+            .map(|title| lazy_map.get_id(title))
+            .collect::<Vec<_>>();
+        assert_eq!(single, expected);
+    }
+
+    #[test]
+    fn decoded_titles_round_trip_through_the_title_map() {
+        let xml = "\
+  <page>
+    <title>AT&amp;T</title>
+    <ns>0</ns>
+    <id>1005</id>
+  </page>
+  <page>
+    <title>Don&#39;t Stop Me Now</title>
+    <ns>0</ns>
+    <id>1006</id>
+  </page>
+";
+        let storage = MemoryStorage::default();
+        let map = memory_title_map(&storage);
+        crate::page::for_each_page(xml.as_bytes(), |page| {
+            map.insert(page.id, page.title.into_owned());
+            Ok(true)
+        })
+        .unwrap();
+        map.mark_loaded();
+        map.serialise().unwrap();
+
+        let lazy_map = memory_title_map(&storage);
+        assert!(lazy_map.deserialise(false).unwrap());
+        for map in [&map, &lazy_map] {
+            assert_eq!(map.get_id("AT&T"), Some(1005));
+            assert_eq!(map.get_id("AT&amp;T"), Some(1005));
+            assert_eq!(map.get_title(1005).as_deref(), Some("AT&T"));
+            assert_eq!(map.get_id("Don't Stop Me Now"), Some(1006));
+        }
+    }
+}